@@ -5,6 +5,44 @@ use serde::{Deserialize, Serialize};
 pub struct AppInfo {
     pub root: String,
     pub name: String,
+    #[serde(default)]
+    pub sessions: Vec<ChatSessionInfo>,
+    #[serde(default)]
+    pub usage: UsageInfo,
+    #[serde(default)]
+    pub usage_today: UsageInfo,
+}
+
+/// Token usage and cost accumulated from claude's `result` events.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageInfo {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+    #[serde(default)]
+    pub cost_usd: f64,
+    #[serde(default)]
+    pub turns: u64,
+}
+
+impl UsageInfo {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn add(&mut self, other: &UsageInfo) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+        self.cost_usd += other.cost_usd;
+        self.turns += other.turns;
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,6 +50,136 @@ pub struct ChatSessionInfo {
     pub id: String,
     pub name: String,
     pub app_root: String,
+    pub created_at: DateTime<Utc>,
+    pub last_active: DateTime<Utc>,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub usage: UsageInfo,
+    /// Branch of the session's own worktree, for isolated sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Session this one was forked from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_from: Option<String>,
+}
+
+/// Claude CLI options for a chat turn. Unset fields fall back to the app's defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub append_system_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub add_dirs: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effort: Option<Effort>,
+    /// Run a new session in its own git worktree and branch; ignored for
+    /// existing sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isolated: Option<bool>,
+}
+
+impl ChatOptions {
+    pub fn is_empty(&self) -> bool {
+        self.model.is_none()
+            && self.max_turns.is_none()
+            && self.append_system_prompt.is_none()
+            && self.add_dirs.is_empty()
+            && self.effort.is_none()
+            && self.isolated.is_none()
+    }
+
+    /// Layer `self` over `defaults`; extra directories from both are kept.
+    pub fn merged_over(&self, defaults: &ChatOptions) -> ChatOptions {
+        let mut add_dirs = defaults.add_dirs.clone();
+        for dir in &self.add_dirs {
+            if !add_dirs.contains(dir) {
+                add_dirs.push(dir.clone());
+            }
+        }
+        ChatOptions {
+            model: self.model.clone().or_else(|| defaults.model.clone()),
+            max_turns: self.max_turns.or(defaults.max_turns),
+            append_system_prompt: self
+                .append_system_prompt
+                .clone()
+                .or_else(|| defaults.append_system_prompt.clone()),
+            add_dirs,
+            effort: self.effort.or(defaults.effort),
+            isolated: self.isolated.or(defaults.isolated),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Effort {
+    Low,
+    Medium,
+    High,
+}
+
+/// Why a turn was refused or stopped by the listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorReason {
+    BudgetExceeded,
+    /// The turn ran longer than the app's `max_turn_secs`
+    TurnTimeout,
+    /// The agent produced no output for `max_idle_secs`
+    IdleTimeout,
+    /// The agent process hit a CPU or memory limit
+    ResourceLimit,
+}
+
+/// UI action requested by the agent through a `<ui_call>` block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum UiCommand {
+    /// App list changed; the listener has already reloaded its config
+    RefreshApps,
+    OpenFile { path: String, line: Option<u32> },
+    ShowDiff {
+        #[serde(default)]
+        paths: Vec<String>,
+    },
+    Notify {
+        message: String,
+        #[serde(default)]
+        level: NotifyLevel,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyLevel {
+    #[default]
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// A file touched during a chat turn. Paths are relative to the repository root;
+/// line counts are None for binary files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChange {
+    pub path: String,
+    pub status: String,
+    pub additions: Option<u64>,
+    pub deletions: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,9 +192,16 @@ pub enum ChatMessage {
 
     /// UI → Server → Listener: user sends a chat message
     ChatInput {
+        #[serde(default)]
+        request_id: String,
         chat_session_id: Option<String>,
         app_root: String,
         content: String,
+        #[serde(default)]
+        options: Option<ChatOptions>,
+        /// Ids of finished uploads to include in this turn
+        #[serde(default)]
+        attachments: Vec<String>,
     },
 
     /// Listener → Server → UI: streaming output from claude
@@ -36,6 +211,21 @@ pub enum ChatMessage {
         timestamp: DateTime<Utc>,
     },
 
+    /// Listener → Server → UI: a line the agent wrote to stderr
+    ChatDiagnostic {
+        chat_session_id: String,
+        content: String,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Listener → Server → UI: the agent asked the UI to do something
+    UiCommand {
+        chat_session_id: String,
+        #[serde(flatten)]
+        command: UiCommand,
+        timestamp: DateTime<Utc>,
+    },
+
     /// Listener → Server → UI: claude finished
     ChatDone {
         chat_session_id: String,
@@ -46,6 +236,9 @@ pub enum ChatMessage {
     ChatError {
         chat_session_id: String,
         error: String,
+        /// Set when the listener refused or stopped the turn on purpose
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<ErrorReason>,
         timestamp: DateTime<Utc>,
     },
 
@@ -54,11 +247,247 @@ pub enum ChatMessage {
         chat_session_id: String,
         app_root: String,
         name: String,
+        /// Worktree branch of an isolated session
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+        /// Session this one was forked from
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        forked_from: Option<String>,
         timestamp: DateTime<Utc>,
     },
 
     /// Server → Listener: request to reload config and resend apps
     ResyncApps,
+
+    /// Listener → Server → UI: claude asks for permission to use a tool
+    PermissionRequest {
+        chat_session_id: String,
+        request_id: String,
+        tool_name: String,
+        input: serde_json::Value,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// UI → Server → Listener: load claude's on-disk transcript for a session.
+    /// `before` is the entry index to page backwards from (defaults to the end).
+    LoadTranscript {
+        chat_session_id: String,
+        before: Option<usize>,
+        limit: Option<usize>,
+    },
+
+    /// Listener → Server → UI: one page of a session transcript, oldest first.
+    /// `cursor` is the `before` value for the next older page, if any.
+    ChatHistory {
+        chat_session_id: String,
+        entries: Vec<String>,
+        cursor: Option<usize>,
+        total: usize,
+    },
+
+    /// UI → Server → Listener: give a session a new display name
+    RenameSession {
+        chat_session_id: String,
+        name: String,
+    },
+
+    /// UI → Server → Listener: hide (or restore) a session in session lists
+    ArchiveSession {
+        chat_session_id: String,
+        archived: bool,
+    },
+
+    /// UI → Server → Listener: forget a session, optionally deleting claude's transcript
+    DeleteSession {
+        chat_session_id: String,
+        #[serde(default)]
+        remove_transcript: bool,
+    },
+
+    /// UI → Server → Listener: start a new session that continues from this
+    /// one's conversation, leaving the original as it is
+    ForkSession { chat_session_id: String },
+
+    /// UI → Server → Listener: one base64 chunk of a file upload. Chunks are
+    /// sent in order; the first one has offset 0 and the final one sets `last`.
+    AttachmentChunk {
+        attachment_id: String,
+        chat_session_id: Option<String>,
+        app_root: String,
+        file_name: String,
+        offset: u64,
+        data: String,
+        #[serde(default)]
+        last: bool,
+    },
+
+    /// Listener → Server → UI: upload finished and can be referenced from ChatInput
+    AttachmentStored {
+        attachment_id: String,
+        path: String,
+        size: u64,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Listener → Server → UI: upload was rejected
+    AttachmentFailed {
+        attachment_id: String,
+        error: String,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Server → Listener: list a directory inside a registered app root
+    ListDir {
+        request_id: String,
+        app_root: String,
+        path: String,
+    },
+
+    /// Server → Listener: read a file inside a registered app root
+    ReadFile {
+        request_id: String,
+        app_root: String,
+        path: String,
+    },
+
+    /// Listener → Server: response to ListDir
+    DirListing {
+        request_id: String,
+        path: String,
+        entries: Vec<DirEntry>,
+        truncated: bool,
+    },
+
+    /// Listener → Server: response to ReadFile. `content` is None for binary files.
+    FileContent {
+        request_id: String,
+        path: String,
+        content: Option<String>,
+        size: u64,
+        truncated: bool,
+        binary: bool,
+    },
+
+    /// Listener → Server → UI: files changed by a finished turn in a git-backed app.
+    /// `base` is a tree of the session's working directory as the turn started,
+    /// so earlier uncommitted changes are not counted; diff it by passing the
+    /// session to GetDiff.
+    TurnChanges {
+        chat_session_id: String,
+        app_root: String,
+        base: String,
+        files: Vec<FileChange>,
+        timestamp: DateTime<Utc>,
+    },
+
+    /// Server → Listener: unified diff of the working tree against `base`
+    /// (defaults to HEAD), optionally limited to some paths. With a session,
+    /// its working directory is diffed, which is a worktree for isolated ones.
+    GetDiff {
+        request_id: String,
+        app_root: String,
+        #[serde(default)]
+        chat_session_id: Option<String>,
+        base: Option<String>,
+        #[serde(default)]
+        paths: Vec<String>,
+    },
+
+    /// Listener → Server: response to GetDiff
+    DiffContent {
+        request_id: String,
+        diff: String,
+        truncated: bool,
+    },
+
+    /// UI → Server → Listener: register an existing directory (`path`) or
+    /// clone `git_url` into the workspace directory as a new app
+    AddApp {
+        request_id: String,
+        path: Option<String>,
+        git_url: Option<String>,
+        /// Directory name for the clone; derived from the URL when absent
+        name: Option<String>,
+    },
+
+    /// Listener → Server: response to AddApp
+    AppAdded { request_id: String, app_root: String },
+
+    /// UI → Server → Listener: unregister an app; its files are left in place
+    RemoveApp { request_id: String, app_root: String },
+
+    /// Listener → Server: response to RemoveApp
+    AppRemoved { request_id: String, app_root: String },
+
+    /// UI → Server → Listener: merge an isolated session's branch into the
+    /// app's checked-out branch, then remove its worktree and archive it
+    MergeSession {
+        request_id: String,
+        chat_session_id: String,
+    },
+
+    /// UI → Server → Listener: drop an isolated session's worktree and
+    /// branch without merging, and archive it
+    DiscardSession {
+        request_id: String,
+        chat_session_id: String,
+    },
+
+    /// Listener → Server: response to MergeSession
+    SessionMerged {
+        request_id: String,
+        chat_session_id: String,
+        branch: String,
+    },
+
+    /// Listener → Server: response to DiscardSession
+    SessionDiscarded {
+        request_id: String,
+        chat_session_id: String,
+        branch: String,
+    },
+
+    /// UI → Server → Listener: start a terminal in an app, running `command`
+    /// or the user's shell. The server adds the linked terminal token the
+    /// terminal attaches under.
+    OpenTerminal {
+        request_id: String,
+        app_root: String,
+        command: Option<String>,
+        #[serde(default)]
+        terminal_token: String,
+    },
+
+    /// Listener → Server: response to OpenTerminal, naming the terminal
+    /// session to show
+    TerminalOpened {
+        request_id: String,
+        terminal_token: String,
+        session_id: String,
+    },
+
+    /// Listener → Server: response to ChatInput once the turn is queued,
+    /// naming its session (new or existing)
+    ChatAccepted {
+        request_id: String,
+        chat_session_id: String,
+    },
+
+    /// Listener → Server: a correlated request could not be served
+    RequestFailed {
+        request_id: String,
+        error: String,
+    },
+
+    /// UI → Server → Listener: user answers a permission request
+    PermissionResponse {
+        request_id: String,
+        allow: bool,
+        message: Option<String>,
+    },
+
+    /// UI → Server → Listener: stop the turn running in a session
+    CancelTurn { chat_session_id: String },
 }
 
 impl ChatMessage {
@@ -81,6 +510,20 @@ impl ChatMessage {
         Self::ChatError {
             chat_session_id: chat_session_id.into(),
             error: error.into(),
+            reason: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    pub fn chat_error_with_reason(
+        chat_session_id: impl Into<String>,
+        error: impl Into<String>,
+        reason: Option<ErrorReason>,
+    ) -> Self {
+        Self::ChatError {
+            chat_session_id: chat_session_id.into(),
+            error: error.into(),
+            reason,
             timestamp: Utc::now(),
         }
     }
@@ -89,12 +532,71 @@ impl ChatMessage {
         chat_session_id: impl Into<String>,
         app_root: impl Into<String>,
         name: impl Into<String>,
+        branch: Option<String>,
+        forked_from: Option<String>,
     ) -> Self {
         Self::ChatSessionCreated {
             chat_session_id: chat_session_id.into(),
             app_root: app_root.into(),
             name: name.into(),
+            branch,
+            forked_from,
+            timestamp: Utc::now(),
+        }
+    }
+
+    pub fn permission_request(
+        chat_session_id: impl Into<String>,
+        request_id: impl Into<String>,
+        tool_name: impl Into<String>,
+        input: serde_json::Value,
+    ) -> Self {
+        Self::PermissionRequest {
+            chat_session_id: chat_session_id.into(),
+            request_id: request_id.into(),
+            tool_name: tool_name.into(),
+            input,
             timestamp: Utc::now(),
         }
     }
+
+    pub fn request_failed(request_id: impl Into<String>, error: impl Into<String>) -> Self {
+        Self::RequestFailed {
+            request_id: request_id.into(),
+            error: error.into(),
+        }
+    }
+
+    /// Request id of a listener response that answers a server request.
+    pub fn response_request_id(&self) -> Option<&str> {
+        match self {
+            Self::DirListing { request_id, .. }
+            | Self::FileContent { request_id, .. }
+            | Self::DiffContent { request_id, .. }
+            | Self::AppAdded { request_id, .. }
+            | Self::AppRemoved { request_id, .. }
+            | Self::SessionMerged { request_id, .. }
+            | Self::SessionDiscarded { request_id, .. }
+            | Self::TerminalOpened { request_id, .. }
+            | Self::ChatAccepted { request_id, .. }
+            | Self::RequestFailed { request_id, .. } => Some(request_id),
+            _ => None,
+        }
+    }
+
+    /// Chat session a listener event belongs to.
+    pub fn event_session_id(&self) -> Option<&str> {
+        match self {
+            Self::ChatOutput { chat_session_id, .. }
+            | Self::ChatDiagnostic { chat_session_id, .. }
+            | Self::UiCommand { chat_session_id, .. }
+            | Self::ChatDone { chat_session_id, .. }
+            | Self::ChatError { chat_session_id, .. }
+            | Self::ChatSessionCreated { chat_session_id, .. }
+            | Self::PermissionRequest { chat_session_id, .. }
+            | Self::ChatHistory { chat_session_id, .. }
+            | Self::TurnChanges { chat_session_id, .. } => Some(chat_session_id),
+            _ => None,
+        }
+    }
 }
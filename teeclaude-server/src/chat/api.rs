@@ -3,17 +3,20 @@ use axum::{
     http::StatusCode,
     response::{
         sse::{Event, Sse},
-        IntoResponse,
+        IntoResponse, Response,
     },
     Json,
 };
 use futures::stream::Stream;
 use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::oneshot;
 use tokio_stream::{wrappers::BroadcastStream, StreamExt};
 
-use teeclaude_common::{ChatMessage, Token, TokenResponse, TokenValidateRequest, TokenValidateResponse};
+use teeclaude_common::{ChatMessage, ChatOptions, Token, TokenResponse, TokenValidateRequest, TokenValidateResponse};
 
+use crate::terminal::TerminalTokenState;
 use crate::AppState;
 use super::ChatTokenState;
 use super::ws::TokenQuery;
@@ -23,7 +26,20 @@ pub async fn create_token(State(state): State<AppState>) -> Json<TokenResponse>
     let token_value = token.value.clone();
     let expires_at = token.expires_at;
 
-    let token_state = ChatTokenState::new(token);
+    // Terminals opened from chat show up under a linked terminal token
+    let terminal_token = Token {
+        expires_at,
+        ..Token::generate()
+    };
+    let terminal_token_value = terminal_token.value.clone();
+    state
+        .terminal
+        .tokens
+        .write()
+        .await
+        .insert(terminal_token_value.clone(), TerminalTokenState::new(terminal_token));
+
+    let token_state = ChatTokenState::new(token, terminal_token_value);
     state
         .chat
         .tokens
@@ -67,21 +83,11 @@ pub async fn validate_token(
     }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct ChatInputRequest {
-    pub token: String,
-    pub chat_session_id: Option<String>,
-    pub app_root: String,
-    pub content: String,
-}
-
-pub async fn chat_input(
-    State(state): State<AppState>,
-    Json(req): Json<ChatInputRequest>,
-) -> impl IntoResponse {
+/// Forward a UI request to the listener connected under `token`.
+async fn send_to_listener(state: &AppState, token: &str, message: ChatMessage) -> StatusCode {
     let tokens = state.chat.tokens.read().await;
 
-    let token_state = match tokens.get(&req.token) {
+    let token_state = match tokens.get(token) {
         Some(ts) if ts.token.is_valid() => ts,
         _ => return StatusCode::UNAUTHORIZED,
     };
@@ -94,18 +100,98 @@ pub async fn chat_input(
     drop(listener);
     drop(tokens);
 
-    let message = ChatMessage::ChatInput {
-        chat_session_id: req.chat_session_id,
-        app_root: req.app_root,
-        content: req.content,
-    };
-
     match sender.send(message).await {
         Ok(_) => StatusCode::OK,
         Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
 
+const LISTENER_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Adding an app may clone a repository
+const ADD_APP_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Send a request to the listener and wait for the response carrying its request id.
+async fn request_listener(
+    state: &AppState,
+    token: &str,
+    timeout: Duration,
+    build: impl FnOnce(String) -> ChatMessage,
+) -> Result<ChatMessage, StatusCode> {
+    let tokens = state.chat.tokens.read().await;
+
+    let token_state = match tokens.get(token) {
+        Some(ts) if ts.token.is_valid() => ts,
+        _ => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    let listener = token_state.listener.read().await;
+    let sender = match listener.as_ref() {
+        Some(conn) => conn.sender.clone(),
+        None => return Err(StatusCode::SERVICE_UNAVAILABLE),
+    };
+    drop(listener);
+    let pending = token_state.pending.clone();
+    drop(tokens);
+
+    let request_id = nanoid::nanoid!(12);
+    let (reply_tx, reply_rx) = oneshot::channel();
+    pending.lock().await.insert(request_id.clone(), reply_tx);
+
+    if sender.send(build(request_id.clone())).await.is_err() {
+        pending.lock().await.remove(&request_id);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    match tokio::time::timeout(timeout, reply_rx).await {
+        Ok(Ok(message)) => Ok(message),
+        Ok(Err(_)) => Err(StatusCode::BAD_GATEWAY),
+        Err(_) => {
+            pending.lock().await.remove(&request_id);
+            Err(StatusCode::GATEWAY_TIMEOUT)
+        }
+    }
+}
+
+fn listener_response(result: Result<ChatMessage, StatusCode>) -> Response {
+    match result {
+        Ok(message @ ChatMessage::RequestFailed { .. }) => {
+            (StatusCode::BAD_REQUEST, Json(message)).into_response()
+        }
+        Ok(message) => Json(message).into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatInputRequest {
+    pub token: String,
+    pub chat_session_id: Option<String>,
+    pub app_root: String,
+    pub content: String,
+    #[serde(default)]
+    pub options: Option<ChatOptions>,
+    #[serde(default)]
+    pub attachments: Vec<String>,
+}
+
+pub async fn chat_input(
+    State(state): State<AppState>,
+    Json(req): Json<ChatInputRequest>,
+) -> impl IntoResponse {
+    let result = request_listener(&state, &req.token, LISTENER_REQUEST_TIMEOUT, |request_id| {
+        ChatMessage::ChatInput {
+            request_id,
+            chat_session_id: req.chat_session_id,
+            app_root: req.app_root,
+            content: req.content,
+            options: req.options,
+            attachments: req.attachments,
+        }
+    })
+    .await;
+    listener_response(result)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RefreshAppsRequest {
     pub token: String,
@@ -115,25 +201,339 @@ pub async fn refresh_apps(
     State(state): State<AppState>,
     Json(req): Json<RefreshAppsRequest>,
 ) -> impl IntoResponse {
-    let tokens = state.chat.tokens.read().await;
+    send_to_listener(&state, &req.token, ChatMessage::ResyncApps).await
+}
 
-    let token_state = match tokens.get(&req.token) {
-        Some(ts) if ts.token.is_valid() => ts,
-        _ => return StatusCode::UNAUTHORIZED,
+#[derive(Debug, Deserialize)]
+pub struct PermissionResponseRequest {
+    pub token: String,
+    pub request_id: String,
+    pub allow: bool,
+    pub message: Option<String>,
+}
+
+pub async fn permission_response(
+    State(state): State<AppState>,
+    Json(req): Json<PermissionResponseRequest>,
+) -> impl IntoResponse {
+    let message = ChatMessage::PermissionResponse {
+        request_id: req.request_id,
+        allow: req.allow,
+        message: req.message,
     };
 
-    let listener = token_state.listener.read().await;
-    let sender = match listener.as_ref() {
-        Some(conn) => conn.sender.clone(),
-        None => return StatusCode::SERVICE_UNAVAILABLE,
+    send_to_listener(&state, &req.token, message).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CancelTurnRequest {
+    pub token: String,
+    pub chat_session_id: String,
+}
+
+pub async fn cancel_turn(
+    State(state): State<AppState>,
+    Json(req): Json<CancelTurnRequest>,
+) -> impl IntoResponse {
+    let message = ChatMessage::CancelTurn {
+        chat_session_id: req.chat_session_id,
     };
-    drop(listener);
-    drop(tokens);
 
-    match sender.send(ChatMessage::ResyncApps).await {
-        Ok(_) => StatusCode::OK,
-        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
-    }
+    send_to_listener(&state, &req.token, message).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TranscriptRequest {
+    pub token: String,
+    pub chat_session_id: String,
+    pub before: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+pub async fn load_transcript(
+    State(state): State<AppState>,
+    Json(req): Json<TranscriptRequest>,
+) -> impl IntoResponse {
+    let message = ChatMessage::LoadTranscript {
+        chat_session_id: req.chat_session_id,
+        before: req.before,
+        limit: req.limit,
+    };
+
+    send_to_listener(&state, &req.token, message).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenameSessionRequest {
+    pub token: String,
+    pub chat_session_id: String,
+    pub name: String,
+}
+
+pub async fn rename_session(
+    State(state): State<AppState>,
+    Json(req): Json<RenameSessionRequest>,
+) -> impl IntoResponse {
+    let message = ChatMessage::RenameSession {
+        chat_session_id: req.chat_session_id,
+        name: req.name,
+    };
+
+    send_to_listener(&state, &req.token, message).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchiveSessionRequest {
+    pub token: String,
+    pub chat_session_id: String,
+    #[serde(default = "default_true")]
+    pub archived: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub async fn archive_session(
+    State(state): State<AppState>,
+    Json(req): Json<ArchiveSessionRequest>,
+) -> impl IntoResponse {
+    let message = ChatMessage::ArchiveSession {
+        chat_session_id: req.chat_session_id,
+        archived: req.archived,
+    };
+
+    send_to_listener(&state, &req.token, message).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteSessionRequest {
+    pub token: String,
+    pub chat_session_id: String,
+    #[serde(default)]
+    pub remove_transcript: bool,
+}
+
+pub async fn delete_session(
+    State(state): State<AppState>,
+    Json(req): Json<DeleteSessionRequest>,
+) -> impl IntoResponse {
+    let message = ChatMessage::DeleteSession {
+        chat_session_id: req.chat_session_id,
+        remove_transcript: req.remove_transcript,
+    };
+
+    send_to_listener(&state, &req.token, message).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForkSessionRequest {
+    pub token: String,
+    pub chat_session_id: String,
+}
+
+pub async fn fork_session(
+    State(state): State<AppState>,
+    Json(req): Json<ForkSessionRequest>,
+) -> impl IntoResponse {
+    let message = ChatMessage::ForkSession {
+        chat_session_id: req.chat_session_id,
+    };
+
+    send_to_listener(&state, &req.token, message).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttachmentChunkRequest {
+    pub token: String,
+    pub attachment_id: String,
+    pub chat_session_id: Option<String>,
+    pub app_root: String,
+    pub file_name: String,
+    pub offset: u64,
+    pub data: String,
+    #[serde(default)]
+    pub last: bool,
+}
+
+pub async fn upload_attachment(
+    State(state): State<AppState>,
+    Json(req): Json<AttachmentChunkRequest>,
+) -> impl IntoResponse {
+    let message = ChatMessage::AttachmentChunk {
+        attachment_id: req.attachment_id,
+        chat_session_id: req.chat_session_id,
+        app_root: req.app_root,
+        file_name: req.file_name,
+        offset: req.offset,
+        data: req.data,
+        last: req.last,
+    };
+
+    send_to_listener(&state, &req.token, message).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FsRequest {
+    pub token: String,
+    pub app_root: String,
+    #[serde(default = "default_fs_path")]
+    pub path: String,
+}
+
+fn default_fs_path() -> String {
+    ".".to_string()
+}
+
+pub async fn list_dir(State(state): State<AppState>, Json(req): Json<FsRequest>) -> Response {
+    let result = request_listener(&state, &req.token, LISTENER_REQUEST_TIMEOUT, |request_id| {
+        ChatMessage::ListDir {
+            request_id,
+            app_root: req.app_root,
+            path: req.path,
+        }
+    })
+    .await;
+    listener_response(result)
+}
+
+pub async fn read_file(State(state): State<AppState>, Json(req): Json<FsRequest>) -> Response {
+    let result = request_listener(&state, &req.token, LISTENER_REQUEST_TIMEOUT, |request_id| {
+        ChatMessage::ReadFile {
+            request_id,
+            app_root: req.app_root,
+            path: req.path,
+        }
+    })
+    .await;
+    listener_response(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffRequest {
+    pub token: String,
+    pub app_root: String,
+    pub chat_session_id: Option<String>,
+    pub base: Option<String>,
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+pub async fn get_diff(State(state): State<AppState>, Json(req): Json<DiffRequest>) -> Response {
+    let result = request_listener(&state, &req.token, LISTENER_REQUEST_TIMEOUT, |request_id| {
+        ChatMessage::GetDiff {
+            request_id,
+            app_root: req.app_root,
+            chat_session_id: req.chat_session_id,
+            base: req.base,
+            paths: req.paths,
+        }
+    })
+    .await;
+    listener_response(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddAppRequest {
+    pub token: String,
+    pub path: Option<String>,
+    pub git_url: Option<String>,
+    pub name: Option<String>,
+}
+
+pub async fn add_app(State(state): State<AppState>, Json(req): Json<AddAppRequest>) -> Response {
+    let result = request_listener(&state, &req.token, ADD_APP_TIMEOUT, |request_id| {
+        ChatMessage::AddApp {
+            request_id,
+            path: req.path,
+            git_url: req.git_url,
+            name: req.name,
+        }
+    })
+    .await;
+    listener_response(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveAppRequest {
+    pub token: String,
+    pub app_root: String,
+}
+
+pub async fn remove_app(
+    State(state): State<AppState>,
+    Json(req): Json<RemoveAppRequest>,
+) -> Response {
+    let result = request_listener(&state, &req.token, LISTENER_REQUEST_TIMEOUT, |request_id| {
+        ChatMessage::RemoveApp {
+            request_id,
+            app_root: req.app_root,
+        }
+    })
+    .await;
+    listener_response(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveSessionRequest {
+    pub token: String,
+    pub chat_session_id: String,
+}
+
+pub async fn merge_session(
+    State(state): State<AppState>,
+    Json(req): Json<ResolveSessionRequest>,
+) -> Response {
+    let result = request_listener(&state, &req.token, LISTENER_REQUEST_TIMEOUT, |request_id| {
+        ChatMessage::MergeSession {
+            request_id,
+            chat_session_id: req.chat_session_id,
+        }
+    })
+    .await;
+    listener_response(result)
+}
+
+pub async fn discard_session(
+    State(state): State<AppState>,
+    Json(req): Json<ResolveSessionRequest>,
+) -> Response {
+    let result = request_listener(&state, &req.token, LISTENER_REQUEST_TIMEOUT, |request_id| {
+        ChatMessage::DiscardSession {
+            request_id,
+            chat_session_id: req.chat_session_id,
+        }
+    })
+    .await;
+    listener_response(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenTerminalRequest {
+    pub token: String,
+    pub app_root: String,
+    #[serde(default)]
+    pub command: Option<String>,
+}
+
+pub async fn open_terminal(
+    State(state): State<AppState>,
+    Json(req): Json<OpenTerminalRequest>,
+) -> Response {
+    let terminal_token = match state.chat.tokens.read().await.get(&req.token) {
+        Some(ts) => ts.terminal_token.clone(),
+        None => return StatusCode::UNAUTHORIZED.into_response(),
+    };
+    let result = request_listener(&state, &req.token, LISTENER_REQUEST_TIMEOUT, |request_id| {
+        ChatMessage::OpenTerminal {
+            request_id,
+            app_root: req.app_root,
+            command: req.command,
+            terminal_token,
+        }
+    })
+    .await;
+    listener_response(result)
 }
 
 pub async fn events(
@@ -2,10 +2,13 @@ pub mod api;
 pub mod ws;
 
 use std::{collections::HashMap, sync::Arc};
-use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex, RwLock};
 
 use teeclaude_common::{AppInfo, ChatMessage, Token};
 
+/// Server requests waiting for the listener response with the same request id
+pub type PendingRequests = Arc<Mutex<HashMap<String, oneshot::Sender<ChatMessage>>>>;
+
 #[derive(Clone, Default)]
 pub struct ChatState {
     pub tokens: Arc<RwLock<HashMap<String, ChatTokenState>>>,
@@ -17,15 +20,20 @@ pub struct ChatTokenState {
     pub tx: broadcast::Sender<ChatMessage>,
     /// The connected listener (if any)
     pub listener: RwLock<Option<ListenerConnection>>,
+    pub pending: PendingRequests,
+    /// Terminal mode token that terminals opened from chat attach under
+    pub terminal_token: String,
 }
 
 impl ChatTokenState {
-    pub fn new(token: Token) -> Self {
+    pub fn new(token: Token, terminal_token: String) -> Self {
         let (tx, _) = broadcast::channel::<ChatMessage>(1000);
         Self {
             token,
             tx,
             listener: RwLock::new(None),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            terminal_token,
         }
     }
 }
@@ -37,6 +37,7 @@ async fn handle_listener_socket(socket: WebSocket, state: AppState, token: Strin
     let (mut ws_sender, mut ws_receiver) = socket.split();
     let (listener_tx, mut listener_rx) = mpsc::channel::<ChatMessage>(100);
     let tx = token_state.tx.clone();
+    let pending = token_state.pending.clone();
 
     // Wait for first message: ListenerReady
     let apps = loop {
@@ -89,6 +90,12 @@ async fn handle_listener_socket(socket: WebSocket, state: AppState, token: Strin
             let Ok(message) = serde_json::from_str::<ChatMessage>(&text) else {
                 continue;
             };
+            if let Some(request_id) = message.response_request_id() {
+                if let Some(reply) = pending.lock().await.remove(request_id) {
+                    let _ = reply.send(message);
+                    continue;
+                }
+            }
             if let ChatMessage::ListenerReady { ref apps } = message {
                 update_listener_apps(&state_clone, &token_clone, apps).await;
             }
@@ -106,6 +113,8 @@ async fn handle_listener_socket(socket: WebSocket, state: AppState, token: Strin
     if let Some(ts) = tokens.get(&token) {
         let mut listener = ts.listener.write().await;
         *listener = None;
+        // Fail outstanding requests right away instead of letting them time out
+        ts.pending.lock().await.clear();
     }
 }
 
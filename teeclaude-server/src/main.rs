@@ -46,6 +46,22 @@ async fn main() -> Result<()> {
         .route("/api/chat/token/validate", post(chat::api::validate_token))
         .route("/api/chat/input", post(chat::api::chat_input))
         .route("/api/chat/refresh-apps", post(chat::api::refresh_apps))
+        .route("/api/chat/permission", post(chat::api::permission_response))
+        .route("/api/chat/cancel", post(chat::api::cancel_turn))
+        .route("/api/chat/transcript", post(chat::api::load_transcript))
+        .route("/api/chat/session/rename", post(chat::api::rename_session))
+        .route("/api/chat/session/archive", post(chat::api::archive_session))
+        .route("/api/chat/session/delete", post(chat::api::delete_session))
+        .route("/api/chat/session/fork", post(chat::api::fork_session))
+        .route("/api/chat/session/merge", post(chat::api::merge_session))
+        .route("/api/chat/session/discard", post(chat::api::discard_session))
+        .route("/api/chat/attachment", post(chat::api::upload_attachment))
+        .route("/api/chat/fs/list", post(chat::api::list_dir))
+        .route("/api/chat/fs/read", post(chat::api::read_file))
+        .route("/api/chat/diff", post(chat::api::get_diff))
+        .route("/api/chat/app/add", post(chat::api::add_app))
+        .route("/api/chat/app/remove", post(chat::api::remove_app))
+        .route("/api/chat/terminal/open", post(chat::api::open_terminal))
         .route("/api/chat/events", get(chat::api::events))
         .route("/api/chat/status", get(chat::api::get_status))
         .route("/ws/listener", get(chat::ws::handle_listener_ws))
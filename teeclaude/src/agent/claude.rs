@@ -0,0 +1,355 @@
+use anyhow::Result;
+use base64::Engine;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::os::unix::process::ExitStatusExt;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, Command};
+use tokio::sync::mpsc;
+
+use teeclaude_common::{ChatOptions, Effort, ErrorReason};
+
+use crate::attachments::Attachment;
+use crate::config::{Limits, PermissionMode};
+use crate::permission::{self, PermissionBroker};
+use crate::sandbox;
+
+use super::{AgentBackend, AgentEvent, RunningTurn, TurnDriver, TurnError, TurnSpec};
+
+/// How a turn relates to claude's stored sessions.
+enum Session<'a> {
+    New,
+    Resume,
+    Fork(&'a str),
+}
+
+/// Runs turns with the `claude` CLI in print mode with stream-json output.
+pub struct ClaudeBackend {
+    permissions: PermissionBroker,
+}
+
+impl ClaudeBackend {
+    pub fn new(permissions: PermissionBroker) -> Self {
+        Self { permissions }
+    }
+
+    fn spawn(&self, spec: TurnSpec, session: Session) -> Result<RunningTurn> {
+        // The prompt goes on stdin: it can be long, may start with a dash
+        // and should not show up in `ps`
+        let mut cmd = match &spec.sandbox {
+            Some(sandbox) => {
+                let socket = (spec.permission_mode == PermissionMode::Prompt)
+                    .then(|| self.permissions.socket_path());
+                Command::from(sandbox::command(
+                    "claude",
+                    &spec.app_root,
+                    &spec.cwd,
+                    sandbox,
+                    &spec.config_path,
+                    socket,
+                )?)
+            }
+            None => Command::new("claude"),
+        };
+        cmd.arg("-p");
+        cmd.arg("--input-format").arg("stream-json");
+        cmd.arg("--output-format").arg("stream-json");
+        cmd.arg("--verbose");
+
+        match session {
+            Session::New => {
+                cmd.arg("--session-id").arg(&spec.session_id);
+            }
+            Session::Resume => {
+                cmd.arg("-r").arg(&spec.session_id);
+            }
+            Session::Fork(parent_id) => {
+                cmd.arg("-r").arg(parent_id).arg("--fork-session");
+                cmd.arg("--session-id").arg(&spec.session_id);
+            }
+        }
+
+        self.apply_allowed_tools(&mut cmd, &spec);
+        apply_options(&mut cmd, &spec.options, &spec.instructions);
+
+        cmd.current_dir(&spec.cwd);
+        apply_limits(&mut cmd, &spec.limits);
+        // Own process group, so cancelling also stops the tools claude started
+        cmd.process_group(0);
+        cmd.stdin(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        let mut child = cmd.spawn()?;
+        if let Some(stdin) = child.stdin.take() {
+            tokio::spawn(write_prompt(stdin, spec.prompt, spec.attachments));
+        }
+        let (driver, turn) = RunningTurn::channel();
+        tokio::spawn(drive(child, driver, spec.limits));
+        Ok(turn)
+    }
+
+    fn apply_allowed_tools(&self, cmd: &mut Command, spec: &TurnSpec) {
+        match spec.permission_mode {
+            PermissionMode::Skip => {
+                cmd.arg("--dangerously-skip-permissions");
+            }
+            PermissionMode::Prompt => {
+                if !spec.allowed_tools.is_empty() {
+                    cmd.arg("--allowedTools").arg(spec.allowed_tools.join(","));
+                }
+                cmd.arg("--mcp-config")
+                    .arg(self.permissions.mcp_config(&spec.session_id).to_string());
+                cmd.arg("--permission-prompt-tool")
+                    .arg(permission::prompt_tool_name());
+            }
+        }
+    }
+}
+
+impl AgentBackend for ClaudeBackend {
+    fn name(&self) -> &'static str {
+        "claude"
+    }
+
+    fn start_turn(&self, spec: TurnSpec) -> Result<RunningTurn> {
+        self.spawn(spec, Session::New)
+    }
+
+    fn resume_session(&self, spec: TurnSpec) -> Result<RunningTurn> {
+        self.spawn(spec, Session::Resume)
+    }
+
+    fn fork_session(&self, spec: TurnSpec, parent_id: &str) -> Result<RunningTurn> {
+        self.spawn(spec, Session::Fork(parent_id))
+    }
+}
+
+fn apply_limits(cmd: &mut Command, limits: &Limits) {
+    let Limits {
+        max_cpu_secs,
+        max_memory_mb,
+        nice,
+        ..
+    } = *limits;
+    if max_cpu_secs.is_none() && max_memory_mb.is_none() && nice.is_none() {
+        return;
+    }
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(secs) = max_cpu_secs {
+                // SIGXCPU at the soft limit, SIGKILL shortly after
+                let limit = rlimit(secs, secs.saturating_add(5));
+                check(libc::setrlimit(libc::RLIMIT_CPU, &limit))?;
+            }
+            if let Some(mb) = max_memory_mb {
+                let bytes = mb.saturating_mul(1024 * 1024);
+                check(libc::setrlimit(libc::RLIMIT_AS, &rlimit(bytes, bytes)))?;
+            }
+            if let Some(n) = nice {
+                check(libc::setpriority(libc::PRIO_PROCESS as _, 0, n))?;
+            }
+            Ok(())
+        });
+    }
+}
+
+fn rlimit(soft: u64, hard: u64) -> libc::rlimit {
+    libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    }
+}
+
+fn check(ret: libc::c_int) -> std::io::Result<()> {
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn apply_options(cmd: &mut Command, options: &ChatOptions, instructions: &str) {
+    if let Some(model) = &options.model {
+        cmd.arg("--model").arg(model);
+    }
+    if let Some(max_turns) = options.max_turns {
+        cmd.arg("--max-turns").arg(max_turns.to_string());
+    }
+    let system_prompt = match &options.append_system_prompt {
+        Some(prompt) if instructions.is_empty() => prompt.clone(),
+        Some(prompt) => format!("{}\n\n{}", instructions.trim_end(), prompt),
+        None => instructions.to_string(),
+    };
+    if !system_prompt.is_empty() {
+        cmd.arg("--append-system-prompt").arg(system_prompt);
+    }
+    for dir in &options.add_dirs {
+        cmd.arg("--add-dir").arg(dir);
+    }
+    if let Some(effort) = options.effort {
+        // Same budgets claude uses for "think" / "think hard" / "ultrathink"
+        let budget = match effort {
+            Effort::Low => 4_000,
+            Effort::Medium => 10_000,
+            Effort::High => 31_999,
+        };
+        cmd.env("MAX_THINKING_TOKENS", budget.to_string());
+    }
+}
+
+/// Images larger than this are only referenced by path in the prompt.
+const MAX_INLINE_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// The user message in claude's stream-json input format, with image
+/// attachments inlined so claude sees them without a tool call.
+async fn user_message(prompt: String, attachments: Vec<Attachment>) -> serde_json::Value {
+    let mut content = vec![json!({ "type": "text", "text": prompt })];
+    for attachment in attachments {
+        let Some(media_type) = attachment.image_type else {
+            continue;
+        };
+        if attachment.size > MAX_INLINE_IMAGE_BYTES {
+            continue;
+        }
+        match tokio::fs::read(&attachment.path).await {
+            Ok(bytes) => content.push(json!({
+                "type": "image",
+                "source": {
+                    "type": "base64",
+                    "media_type": media_type,
+                    "data": base64::engine::general_purpose::STANDARD.encode(bytes),
+                },
+            })),
+            Err(e) => eprintln!(
+                "[chat] failed to read image {}: {}",
+                attachment.path.display(),
+                e
+            ),
+        }
+    }
+    json!({
+        "type": "user",
+        "message": { "role": "user", "content": content },
+    })
+}
+
+/// Write the prompt and close stdin, which tells claude the input is complete.
+async fn write_prompt(mut stdin: ChildStdin, prompt: String, attachments: Vec<Attachment>) {
+    let mut line = user_message(prompt, attachments).await.to_string();
+    line.push('\n');
+    if let Err(e) = stdin.write_all(line.as_bytes()).await {
+        eprintln!("[chat] failed to write prompt to claude: {}", e);
+    }
+}
+
+/// Number of stderr lines kept for the error message of a failed turn.
+const STDERR_TAIL_LINES: usize = 20;
+const MAX_STDERR_LINE: usize = 2000;
+/// How long to wait for stderr to close after claude exited; subprocesses
+/// that inherited it can keep it open.
+const STDERR_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Forward stderr lines as they arrive and return the last few.
+async fn read_stderr(stderr: ChildStderr, events: mpsc::Sender<AgentEvent>) -> String {
+    let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(mut line)) = lines.next_line().await {
+        if line.len() > MAX_STDERR_LINE {
+            let mut end = MAX_STDERR_LINE;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.truncate(end);
+        }
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line.clone());
+        let _ = events.send(AgentEvent::Diagnostic(line)).await;
+    }
+    Vec::from(tail).join("\n")
+}
+
+fn kill_process_group(child: &mut Child) {
+    match child.id() {
+        Some(pid) => unsafe {
+            libc::kill(-(pid as i32), libc::SIGKILL);
+        },
+        None => {
+            let _ = child.start_kill();
+        }
+    }
+}
+
+async fn drive(mut child: Child, driver: TurnDriver, limits: Limits) {
+    let mut cancelled = false;
+
+    // Drain stderr alongside stdout so a full pipe can never block claude
+    let stderr_task = child
+        .stderr
+        .take()
+        .map(|stderr| tokio::spawn(read_stderr(stderr, driver.events.clone())));
+
+    // Stream stdout: forward raw stream-json lines to UI
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        loop {
+            tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => driver.output(line).await,
+                    _ => break,
+                },
+                _ = driver.cancelled() => {
+                    kill_process_group(&mut child);
+                    cancelled = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    let exit = child.wait().await;
+    let stderr_tail = match stderr_task {
+        Some(mut task) => match tokio::time::timeout(STDERR_DRAIN_TIMEOUT, &mut task).await {
+            Ok(Ok(tail)) => tail,
+            _ => {
+                task.abort();
+                String::new()
+            }
+        },
+        None => String::new(),
+    };
+
+    let outcome = match exit {
+        _ if cancelled => Err(TurnError::new("turn cancelled")),
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) if status.signal() == Some(libc::SIGXCPU) => Err(TurnError::with_reason(
+            "claude exceeded its CPU time limit",
+            ErrorReason::ResourceLimit,
+        )),
+        Ok(status) => {
+            let mut message = format!("claude exited with status {}", status);
+            if !stderr_tail.is_empty() {
+                message = format!("{}: {}", message, stderr_tail.trim());
+            }
+            // Running out of address space shows up as an abort, a crash or
+            // the OOM killer; other signals are not the memory limit
+            let memory_signal = matches!(
+                status.signal(),
+                Some(libc::SIGKILL | libc::SIGSEGV | libc::SIGABRT)
+            );
+            match (memory_signal, limits.max_memory_mb) {
+                (true, Some(mb)) => Err(TurnError::with_reason(
+                    format!("{} (memory limit {} MB)", message, mb),
+                    ErrorReason::ResourceLimit,
+                )),
+                _ => Err(TurnError::new(message)),
+            }
+        }
+        Err(e) => Err(TurnError::new(e.to_string())),
+    };
+
+    driver.finish(outcome);
+}
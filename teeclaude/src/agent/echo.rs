@@ -0,0 +1,96 @@
+use anyhow::Result;
+use serde_json::json;
+use std::time::Duration;
+
+use super::{AgentBackend, RunningTurn, TurnError, TurnSpec};
+
+/// Deterministic backend for running the listener without claude installed.
+///
+/// Replays `script` (one stream-json event per line) when configured, and
+/// otherwise answers every prompt by echoing it back.
+pub struct EchoBackend {
+    script: Vec<String>,
+    delay: Duration,
+}
+
+impl EchoBackend {
+    pub fn new(script: Vec<String>, delay_ms: u64) -> Self {
+        Self {
+            script,
+            delay: Duration::from_millis(delay_ms),
+        }
+    }
+
+    fn run(&self, spec: TurnSpec) -> RunningTurn {
+        let lines = if self.script.is_empty() {
+            echo_lines(&spec)
+        } else {
+            self.script.clone()
+        };
+        let delay = self.delay;
+
+        let (driver, turn) = RunningTurn::channel();
+        tokio::spawn(async move {
+            for line in lines {
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => driver.output(line).await,
+                    _ = driver.cancelled() => {
+                        driver.finish(Err(TurnError::new("turn cancelled")));
+                        return;
+                    }
+                }
+            }
+            driver.finish(Ok(()));
+        });
+        turn
+    }
+}
+
+impl AgentBackend for EchoBackend {
+    fn name(&self) -> &'static str {
+        "echo"
+    }
+
+    fn start_turn(&self, spec: TurnSpec) -> Result<RunningTurn> {
+        Ok(self.run(spec))
+    }
+
+    fn resume_session(&self, spec: TurnSpec) -> Result<RunningTurn> {
+        Ok(self.run(spec))
+    }
+
+    fn fork_session(&self, spec: TurnSpec, _parent_id: &str) -> Result<RunningTurn> {
+        Ok(self.run(spec))
+    }
+}
+
+fn echo_lines(spec: &TurnSpec) -> Vec<String> {
+    [
+        json!({
+            "type": "system",
+            "subtype": "init",
+            "session_id": spec.session_id,
+            "cwd": spec.cwd,
+        }),
+        json!({
+            "type": "assistant",
+            "session_id": spec.session_id,
+            "message": {
+                "role": "assistant",
+                "content": [{ "type": "text", "text": spec.prompt }],
+            },
+        }),
+        json!({
+            "type": "result",
+            "subtype": "success",
+            "is_error": false,
+            "session_id": spec.session_id,
+            "result": spec.prompt,
+            "total_cost_usd": 0.0,
+            "usage": { "input_tokens": 0, "output_tokens": 0 },
+        }),
+    ]
+    .iter()
+    .map(|v| v.to_string())
+    .collect()
+}
@@ -0,0 +1,183 @@
+mod claude;
+mod echo;
+
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot, Notify};
+
+use teeclaude_common::{ChatOptions, ErrorReason, UsageInfo};
+
+use crate::attachments::Attachment;
+use crate::config::{BackendConfig, Limits, PermissionMode, Sandbox};
+use crate::permission::PermissionBroker;
+
+pub use claude::ClaudeBackend;
+pub use echo::EchoBackend;
+
+/// Everything a backend needs to run one chat turn.
+pub struct TurnSpec {
+    pub session_id: String,
+    pub app_root: String,
+    /// Where the agent runs: the app root, or an isolated session's worktree
+    pub cwd: String,
+    pub prompt: String,
+    /// Files uploaded with the message; already listed in `prompt`
+    pub attachments: Vec<Attachment>,
+    /// TeeClaude's own instructions, added to the agent's system prompt
+    pub instructions: String,
+    pub options: ChatOptions,
+    pub permission_mode: PermissionMode,
+    pub allowed_tools: Vec<String>,
+    pub limits: Limits,
+    /// Run the agent confined to `cwd`; None runs it unsandboxed
+    pub sandbox: Option<Sandbox>,
+    /// The listener's config file, kept read-only in the sandbox
+    pub config_path: PathBuf,
+}
+
+/// Why a turn failed, as reported to the UI.
+pub struct TurnError {
+    pub message: String,
+    pub reason: Option<ErrorReason>,
+}
+
+impl TurnError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            reason: None,
+        }
+    }
+
+    pub fn with_reason(message: impl Into<String>, reason: ErrorReason) -> Self {
+        Self {
+            message: message.into(),
+            reason: Some(reason),
+        }
+    }
+}
+
+pub enum AgentEvent {
+    /// One line of stream-json output, forwarded to the UI as is
+    Output(String),
+    /// A line of diagnostic output (stderr)
+    Diagnostic(String),
+    /// The agent's final result for the turn
+    Result { is_error: bool, usage: UsageInfo },
+}
+
+/// Cancels a running turn; safe to call after the turn has finished.
+#[derive(Clone)]
+pub struct CancelHandle(Arc<Notify>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.notify_one();
+    }
+}
+
+/// A turn in progress: drain `events`, then `wait` for the outcome.
+pub struct RunningTurn {
+    pub events: mpsc::Receiver<AgentEvent>,
+    cancel: CancelHandle,
+    done: oneshot::Receiver<Result<(), TurnError>>,
+}
+
+impl RunningTurn {
+    fn channel() -> (TurnDriver, RunningTurn) {
+        let (events_tx, events) = mpsc::channel(100);
+        let (done_tx, done) = oneshot::channel();
+        let cancel = CancelHandle(Arc::new(Notify::new()));
+        let driver = TurnDriver {
+            events: events_tx,
+            cancel: cancel.clone(),
+            done: done_tx,
+        };
+        (driver, RunningTurn { events, cancel, done })
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub async fn wait(self) -> Result<(), TurnError> {
+        self.done
+            .await
+            .unwrap_or_else(|_| Err(TurnError::new("agent task ended unexpectedly")))
+    }
+}
+
+/// Backend side of a RunningTurn.
+struct TurnDriver {
+    events: mpsc::Sender<AgentEvent>,
+    cancel: CancelHandle,
+    done: oneshot::Sender<Result<(), TurnError>>,
+}
+
+impl TurnDriver {
+    async fn cancelled(&self) {
+        self.cancel.0.notified().await;
+    }
+
+    /// Forward an output line, plus a Result event when it is the final result.
+    async fn output(&self, line: String) {
+        let result = parse_result_event(&line);
+        let _ = self.events.send(AgentEvent::Output(line)).await;
+        if let Some(result) = result {
+            let _ = self.events.send(result).await;
+        }
+    }
+
+    fn finish(self, outcome: Result<(), TurnError>) {
+        drop(self.events);
+        let _ = self.done.send(outcome);
+    }
+}
+
+pub trait AgentBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Start a turn in a new session with id `spec.session_id`.
+    fn start_turn(&self, spec: TurnSpec) -> Result<RunningTurn>;
+
+    /// Continue an existing session.
+    fn resume_session(&self, spec: TurnSpec) -> Result<RunningTurn>;
+
+    /// Start `spec.session_id` as a copy of `parent_id`'s conversation so far.
+    fn fork_session(&self, spec: TurnSpec, parent_id: &str) -> Result<RunningTurn>;
+}
+
+pub fn backend_for(config: &BackendConfig, permissions: &PermissionBroker) -> Box<dyn AgentBackend> {
+    match config {
+        BackendConfig::Claude => Box::new(ClaudeBackend::new(permissions.clone())),
+        BackendConfig::Echo { script, delay_ms } => {
+            Box::new(EchoBackend::new(script.clone(), *delay_ms))
+        }
+    }
+}
+
+/// Parse claude's stream-json `result` event.
+fn parse_result_event(line: &str) -> Option<AgentEvent> {
+    let v: serde_json::Value = serde_json::from_str(line).ok()?;
+    if v.get("type")?.as_str()? != "result" {
+        return None;
+    }
+    let tokens = |key: &str| {
+        v.get("usage")
+            .and_then(|u| u.get(key))
+            .and_then(|n| n.as_u64())
+            .unwrap_or(0)
+    };
+    Some(AgentEvent::Result {
+        is_error: v.get("is_error")?.as_bool()?,
+        usage: UsageInfo {
+            input_tokens: tokens("input_tokens"),
+            output_tokens: tokens("output_tokens"),
+            cache_creation_input_tokens: tokens("cache_creation_input_tokens"),
+            cache_read_input_tokens: tokens("cache_read_input_tokens"),
+            cost_usd: v.get("total_cost_usd").and_then(|c| c.as_f64()).unwrap_or(0.0),
+            turns: 1,
+        },
+    })
+}
@@ -0,0 +1,297 @@
+use anyhow::{anyhow, bail, Result};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::sync::mpsc;
+
+use teeclaude_common::ChatMessage;
+
+use crate::config::ConfigHandle;
+use crate::listener;
+
+/// Canonicalize `path` and make sure it lies under one of the allowed bases.
+pub async fn confine(config: &ConfigHandle, path: &Path) -> Result<PathBuf> {
+    let resolved = tokio::fs::canonicalize(path)
+        .await
+        .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    let bases = config.read(|c| c.allowed_bases()).await?;
+    for base in bases {
+        if let Ok(base) = tokio::fs::canonicalize(&base).await {
+            if resolved.starts_with(&base) {
+                return Ok(resolved);
+            }
+        }
+    }
+    bail!("{} is outside the allowed base directories", path.display())
+}
+
+/// Resolve an app root sent by the browser to a registered app inside the
+/// allowed base directories.
+pub async fn resolve_app_root(config: &ConfigHandle, app_root: &str) -> Result<String> {
+    let canonical = confine(config, Path::new(app_root))
+        .await?
+        .to_string_lossy()
+        .to_string();
+    // Roots are stored canonicalized, but hand-edited entries may not be
+    let candidates = [canonical, app_root.to_string()];
+    let registered = config
+        .read(move |c| candidates.into_iter().find(|r| c.has_app(r)))
+        .await?;
+    registered.ok_or_else(|| anyhow!("unknown app root: {}", app_root))
+}
+
+/// Accept only remote URLs, so `ext::` commands and local paths never reach git.
+fn validate_git_url(url: &str) -> Result<()> {
+    let remote_scheme = ["https://", "http://", "ssh://", "git://"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme));
+    // scp-like syntax: user@host:path
+    let scp_like = match url.split_once(':') {
+        Some((host, path)) => host.contains('@') && !host.contains('/') && !path.is_empty(),
+        None => false,
+    };
+    if url.starts_with('-') || url.chars().any(char::is_whitespace) || !(remote_scheme || scp_like) {
+        bail!("unsupported git URL: {}", url);
+    }
+    Ok(())
+}
+
+fn clone_name(url: &str, name: Option<&str>) -> Result<String> {
+    let name = match name {
+        Some(n) => n.trim().to_string(),
+        None => url
+            .trim_end_matches('/')
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches(".git")
+            .to_string(),
+    };
+    let valid = !name.is_empty()
+        && !name.starts_with(['.', '-'])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    if !valid {
+        bail!("invalid app directory name: {:?}", name);
+    }
+    Ok(name)
+}
+
+async fn clone(config: &ConfigHandle, url: &str, name: Option<&str>) -> Result<PathBuf> {
+    validate_git_url(url)?;
+    let name = clone_name(url, name)?;
+
+    let workspace = config.read(|c| c.workspace_dir()).await?;
+    tokio::fs::create_dir_all(&workspace).await?;
+    let workspace = confine(config, &workspace).await?;
+    let target = workspace.join(&name);
+    if tokio::fs::try_exists(&target).await? {
+        bail!("{} already exists", target.display());
+    }
+
+    eprintln!("[apps] cloning {} into {}", url, target.display());
+    let output = tokio::process::Command::new("git")
+        .args(["clone", "--quiet", "--", url])
+        .arg(&target)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!(
+            "git clone failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(target)
+}
+
+async fn add_app(
+    config: &ConfigHandle,
+    path: Option<&str>,
+    git_url: Option<&str>,
+    name: Option<&str>,
+) -> Result<String> {
+    let dir = match (path, git_url) {
+        (Some(path), None) => {
+            // Relative paths are taken from the workspace directory
+            let workspace = config.read(|c| c.workspace_dir()).await?;
+            workspace.join(path)
+        }
+        (None, Some(url)) => clone(config, url, name).await?,
+        _ => bail!("exactly one of path and git_url is required"),
+    };
+
+    let root = confine(config, &dir).await?;
+    if !root.is_dir() {
+        bail!("not a directory: {}", root.display());
+    }
+
+    let root = root.to_string_lossy().to_string();
+    let app_root = root.clone();
+    config
+        .update(move |c| {
+            c.ensure_app(&root);
+        })
+        .await?;
+    eprintln!("[apps] added app: {}", app_root);
+    Ok(app_root)
+}
+
+pub async fn handle_add_app(
+    config: &ConfigHandle,
+    out_tx: &mpsc::Sender<ChatMessage>,
+    request_id: &str,
+    path: Option<&str>,
+    git_url: Option<&str>,
+    name: Option<&str>,
+) {
+    let message = match add_app(config, path, git_url, name).await {
+        Ok(app_root) => {
+            listener::send_listener_ready(config, out_tx).await;
+            ChatMessage::AppAdded {
+                request_id: request_id.to_string(),
+                app_root,
+            }
+        }
+        Err(e) => {
+            eprintln!("[apps] failed to add app: {}", e);
+            ChatMessage::request_failed(request_id, e.to_string())
+        }
+    };
+    let _ = out_tx.send(message).await;
+}
+
+pub async fn handle_remove_app(
+    config: &ConfigHandle,
+    out_tx: &mpsc::Sender<ChatMessage>,
+    request_id: &str,
+    app_root: &str,
+) {
+    let root = app_root.to_string();
+    let message = match config.update(move |c| c.remove_app(&root)).await {
+        Ok(true) => {
+            eprintln!("[apps] removed app: {}", app_root);
+            listener::send_listener_ready(config, out_tx).await;
+            ChatMessage::AppRemoved {
+                request_id: request_id.to_string(),
+                app_root: app_root.to_string(),
+            }
+        }
+        Ok(false) => ChatMessage::request_failed(request_id, format!("unknown app root: {}", app_root)),
+        Err(e) => ChatMessage::request_failed(request_id, e.to_string()),
+    };
+    let _ = out_tx.send(message).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_git_urls_are_accepted() {
+        for url in [
+            "https://github.com/bencode/tee-claude.git",
+            "http://example.com/repo",
+            "ssh://git@example.com:2222/repo.git",
+            "git://example.com/repo.git",
+            "git@github.com:bencode/tee-claude.git",
+        ] {
+            assert!(validate_git_url(url).is_ok(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn local_and_helper_git_urls_are_rejected() {
+        for url in [
+            "file:///etc",
+            "/home/me/repo",
+            "../repo",
+            "ext::sh -c touch% /tmp/pwned",
+            "ext::ssh@host:repo",
+            "fd::3",
+            "-uhttps://example.com/repo",
+            "--upload-pack=touch /tmp/x",
+            "github.com:repo",
+            "user@host/dir:repo",
+            "https://example.com/a repo",
+            "",
+        ] {
+            assert!(validate_git_url(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn clone_names_come_from_the_last_path_segment() {
+        assert_eq!(clone_name("https://github.com/bencode/tee-claude.git", None).unwrap(), "tee-claude");
+        assert_eq!(clone_name("git@github.com:repo.git/", None).unwrap(), "repo");
+        assert_eq!(clone_name("https://x/y.git", Some(" mine ")).unwrap(), "mine");
+        assert!(clone_name("https://x/..", None).is_err());
+        assert!(clone_name("https://x/y", Some("../escape")).is_err());
+        assert!(clone_name("https://x/y", Some("-rf")).is_err());
+    }
+
+    /// A listener started in `base`, with apps registered at `base/app`, at the
+    /// non-canonical `base/other/../app2` and at `outside/app` beyond the
+    /// allowed bases. `base/app/escape` links out of the base.
+    async fn setup() -> (PathBuf, ConfigHandle) {
+        let dir = std::env::temp_dir().join(format!("teeclaude-apps-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = std::fs::canonicalize(&dir).unwrap();
+        for sub in ["base/app", "base/other", "outside/app"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        std::fs::write(dir.join("base/app/a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), dir.join("base/app/escape")).unwrap();
+        std::os::unix::fs::symlink(dir.join("base/app"), dir.join("base/link")).unwrap();
+
+        let mut config = crate::config::Config::load_or_create(&dir.join("base").to_string_lossy()).unwrap();
+        for root in ["base/app", "base/other/../app2", "outside/app"] {
+            config.ensure_app(&dir.join(root).to_string_lossy());
+        }
+        config.save().unwrap();
+        (dir, ConfigHandle::spawn(config))
+    }
+
+    #[tokio::test]
+    async fn confine_keeps_paths_under_the_allowed_bases() {
+        let (dir, config) = setup().await;
+        let inside = confine(&config, &dir.join("base/app/a.txt")).await.ok();
+        let dotdot = confine(&config, &dir.join("base/app/../../outside/app")).await;
+        let absolute = confine(&config, &dir.join("outside/app")).await;
+        let symlink = confine(&config, &dir.join("base/app/escape/app")).await;
+        let missing = confine(&config, &dir.join("base/missing")).await;
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(inside, Some(dir.join("base/app/a.txt")));
+        assert!(dotdot.is_err());
+        assert!(absolute.is_err());
+        assert!(symlink.is_err());
+        assert!(missing.is_err());
+    }
+
+    #[tokio::test]
+    async fn app_roots_must_be_registered_and_inside_the_bases() {
+        let (dir, config) = setup().await;
+        std::fs::create_dir_all(dir.join("base/app2")).unwrap();
+        let path = |p: &str| dir.join(p).to_string_lossy().to_string();
+
+        let canonical = resolve_app_root(&config, &path("base/app")).await.ok();
+        let via_link = resolve_app_root(&config, &path("base/link")).await.ok();
+        let via_dotdot = resolve_app_root(&config, &path("base/other/../app")).await.ok();
+        // A hand-edited root that is not canonical still matches as written
+        let non_canonical = resolve_app_root(&config, &path("base/other/../app2")).await.ok();
+        let unregistered = resolve_app_root(&config, &path("base/other")).await;
+        let outside_bases = resolve_app_root(&config, &path("outside/app")).await;
+        let escaping = resolve_app_root(&config, &path("base/app/escape/app")).await;
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(canonical, Some(path("base/app")));
+        assert_eq!(via_link, Some(path("base/app")));
+        assert_eq!(via_dotdot, Some(path("base/app")));
+        assert_eq!(non_canonical, Some(path("base/other/../app2")));
+        assert!(unregistered.is_err());
+        assert!(outside_bases.is_err());
+        assert!(escaping.is_err());
+    }
+}
@@ -0,0 +1,319 @@
+use anyhow::{anyhow, bail, Result};
+use base64::Engine;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Scratch directory under each app root, kept out of git by its own `.gitignore`.
+pub const SCRATCH_DIR: &str = ".teeclaude";
+const PENDING_DIR: &str = "pending";
+const MAX_ID_LEN: usize = 64;
+const MAX_FILE_NAME_LEN: usize = 128;
+/// Uploads older than this are removed when the listener starts
+const MAX_UPLOAD_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+];
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Media type for files claude can take as image input
+    pub image_type: Option<&'static str>,
+}
+
+struct Upload {
+    app_root: String,
+    attachment: Attachment,
+    complete: bool,
+}
+
+pub struct Chunk {
+    pub attachment_id: String,
+    pub chat_session_id: Option<String>,
+    pub app_root: String,
+    pub file_name: String,
+    pub offset: u64,
+    pub data: String,
+    pub last: bool,
+}
+
+/// Tracks uploads from the browser until a chat turn claims them.
+#[derive(Clone, Default)]
+pub struct AttachmentStore {
+    uploads: Arc<Mutex<HashMap<String, Upload>>>,
+}
+
+impl AttachmentStore {
+    /// Append one chunk; returns the finished attachment once the last chunk is written.
+    pub async fn write_chunk(&self, chunk: Chunk, max_bytes: u64) -> Result<Option<Attachment>> {
+        validate_id(&chunk.attachment_id)?;
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(chunk.data.as_bytes())
+            .map_err(|e| anyhow!("invalid base64 data: {}", e))?;
+
+        let mut uploads = self.uploads.lock().await;
+
+        if chunk.offset == 0 {
+            let file_name = sanitize_file_name(&chunk.file_name)?;
+            let session_dir = match &chunk.chat_session_id {
+                Some(id) => {
+                    validate_id(id)?;
+                    id.as_str()
+                }
+                None => PENDING_DIR,
+            };
+            let dir = attachments_dir(&chunk.app_root)
+                .join(session_dir)
+                .join(&chunk.attachment_id);
+            tokio::fs::create_dir_all(&dir).await?;
+            let path = dir.join(&file_name);
+            tokio::fs::File::create(&path).await?;
+
+            uploads.insert(
+                chunk.attachment_id.clone(),
+                Upload {
+                    app_root: chunk.app_root.clone(),
+                    attachment: Attachment {
+                        path,
+                        size: 0,
+                        image_type: image_type(&file_name),
+                    },
+                    complete: false,
+                },
+            );
+        }
+
+        let upload = uploads
+            .get_mut(&chunk.attachment_id)
+            .ok_or_else(|| anyhow!("unknown attachment: {}", chunk.attachment_id))?;
+        if upload.complete {
+            bail!("attachment already complete");
+        }
+        if upload.app_root != chunk.app_root {
+            bail!("attachment belongs to another app");
+        }
+        if chunk.offset != upload.attachment.size {
+            bail!(
+                "unexpected chunk offset {} (expected {})",
+                chunk.offset,
+                upload.attachment.size
+            );
+        }
+
+        let new_size = upload.attachment.size + data.len() as u64;
+        if new_size > max_bytes {
+            let path = upload.attachment.path.clone();
+            uploads.remove(&chunk.attachment_id);
+            let _ = tokio::fs::remove_file(&path).await;
+            bail!("attachment exceeds the {} byte limit", max_bytes);
+        }
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&upload.attachment.path)
+            .await?;
+        file.write_all(&data).await?;
+        file.flush().await?;
+        upload.attachment.size = new_size;
+
+        if chunk.last {
+            upload.complete = true;
+            return Ok(Some(upload.attachment.clone()));
+        }
+        Ok(None)
+    }
+
+    /// Claim finished uploads for a chat turn, moving uploads made before the
+    /// session existed into the session's scratch directory.
+    pub async fn take(
+        &self,
+        ids: &[String],
+        app_root: &str,
+        chat_session_id: &str,
+    ) -> Result<Vec<Attachment>> {
+        let mut uploads = self.uploads.lock().await;
+        for id in ids {
+            match uploads.get(id) {
+                Some(u) if u.complete && u.app_root == app_root => {}
+                Some(u) if u.app_root != app_root => bail!("attachment belongs to another app: {}", id),
+                Some(_) => bail!("attachment upload not finished: {}", id),
+                None => bail!("unknown attachment: {}", id),
+            }
+        }
+
+        let session_dir = attachments_dir(app_root).join(chat_session_id);
+        let mut attachments = Vec::with_capacity(ids.len());
+        for id in ids {
+            let Some(upload) = uploads.remove(id) else {
+                continue;
+            };
+            let mut attachment = upload.attachment;
+            if !attachment.path.starts_with(&session_dir) {
+                if let Some(file_name) = attachment.path.file_name() {
+                    let dir = session_dir.join(id);
+                    tokio::fs::create_dir_all(&dir).await?;
+                    let target = dir.join(file_name);
+                    tokio::fs::rename(&attachment.path, &target).await?;
+                    if let Some(old_dir) = attachment.path.parent() {
+                        let _ = tokio::fs::remove_dir(old_dir).await;
+                    }
+                    attachment.path = target;
+                }
+            }
+            attachments.push(attachment);
+        }
+        Ok(attachments)
+    }
+}
+
+/// TeeClaude's scratch directory in an app, created with its `.gitignore`.
+pub fn scratch_dir(app_root: &str) -> PathBuf {
+    let dir = Path::new(app_root).join(SCRATCH_DIR);
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        let _ = std::fs::create_dir_all(&dir);
+        let _ = std::fs::write(&gitignore, "*\n");
+    }
+    dir
+}
+
+fn attachments_dir(app_root: &str) -> PathBuf {
+    scratch_dir(app_root).join("attachments")
+}
+
+/// Remove everything uploaded to a session.
+pub async fn remove_session(app_root: &str, chat_session_id: &str) -> Result<()> {
+    validate_id(chat_session_id)?;
+    let dir = Path::new(app_root)
+        .join(SCRATCH_DIR)
+        .join("attachments")
+        .join(chat_session_id);
+    match tokio::fs::remove_dir_all(&dir).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Remove uploads older than `MAX_UPLOAD_AGE` from an app, including ones
+/// never claimed by a turn. Returns how many were removed.
+pub async fn remove_expired(app_root: &str) -> Result<usize> {
+    let dir = Path::new(app_root).join(SCRATCH_DIR).join("attachments");
+    let Ok(mut sessions) = tokio::fs::read_dir(&dir).await else {
+        return Ok(0);
+    };
+    let now = SystemTime::now();
+    let mut removed = 0;
+    while let Some(session) = sessions.next_entry().await? {
+        let Ok(mut uploads) = tokio::fs::read_dir(session.path()).await else {
+            continue;
+        };
+        let mut kept = 0;
+        while let Some(upload) = uploads.next_entry().await? {
+            let modified = upload.metadata().await.and_then(|m| m.modified());
+            let expired = modified
+                .ok()
+                .and_then(|m| now.duration_since(m).ok())
+                .is_some_and(|age| age > MAX_UPLOAD_AGE);
+            if expired && tokio::fs::remove_dir_all(upload.path()).await.is_ok() {
+                removed += 1;
+            } else {
+                kept += 1;
+            }
+        }
+        if kept == 0 {
+            let _ = tokio::fs::remove_dir(session.path()).await;
+        }
+    }
+    Ok(removed)
+}
+
+fn validate_id(id: &str) -> Result<()> {
+    let valid = !id.is_empty()
+        && id.len() <= MAX_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!("invalid id: {:?}", id);
+    }
+    Ok(())
+}
+
+fn sanitize_file_name(name: &str) -> Result<String> {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let cleaned: String = base
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_. ".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(MAX_FILE_NAME_LEN)
+        .collect();
+    let cleaned = cleaned.trim().trim_start_matches('.').to_string();
+    if cleaned.is_empty() {
+        bail!("invalid file name: {:?}", name);
+    }
+    Ok(cleaned)
+}
+
+fn image_type(file_name: &str) -> Option<&'static str> {
+    let ext = Path::new(file_name).extension()?.to_str()?.to_ascii_lowercase();
+    IMAGE_TYPES
+        .iter()
+        .find(|(e, _)| *e == ext)
+        .map(|(_, media)| *media)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_keeps_plain_names() {
+        assert_eq!(sanitize_file_name("photo 1.png").unwrap(), "photo 1.png");
+        assert_eq!(sanitize_file_name("a+b?.txt").unwrap(), "a_b_.txt");
+    }
+
+    #[test]
+    fn sanitize_drops_directories() {
+        assert_eq!(sanitize_file_name("/etc/passwd").unwrap(), "passwd");
+        assert_eq!(sanitize_file_name("../../secret.txt").unwrap(), "secret.txt");
+        assert_eq!(sanitize_file_name("C:\\Users\\me\\notes.md").unwrap(), "notes.md");
+    }
+
+    #[test]
+    fn sanitize_rejects_dot_names() {
+        assert!(sanitize_file_name("..").is_err());
+        assert!(sanitize_file_name("../..").is_err());
+        assert!(sanitize_file_name("dir/").is_err());
+        assert!(sanitize_file_name("").is_err());
+        assert_eq!(sanitize_file_name(".bashrc").unwrap(), "bashrc");
+    }
+
+    #[test]
+    fn sanitize_truncates_long_names() {
+        let name = "x".repeat(500);
+        assert_eq!(sanitize_file_name(&name).unwrap().len(), MAX_FILE_NAME_LEN);
+    }
+
+    #[test]
+    fn ids_are_single_path_components() {
+        assert!(validate_id("0b5f-4c1e_9").is_ok());
+        assert!(validate_id("..").is_err());
+        assert!(validate_id("a/b").is_err());
+        assert!(validate_id("").is_err());
+    }
+}
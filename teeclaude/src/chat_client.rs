@@ -0,0 +1,260 @@
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+
+use teeclaude_common::ChatMessage;
+
+use crate::url;
+
+/// How chat events are written to stdout.
+#[derive(Clone, Copy)]
+pub enum Format {
+    /// Assistant text and one line per tool call
+    Text,
+    /// Every event as one JSON object per line
+    Json,
+}
+
+/// Chat events from the server's SSE endpoint.
+struct EventStream {
+    response: reqwest::Response,
+    buf: Vec<u8>,
+    data: String,
+}
+
+impl EventStream {
+    async fn connect(client: &reqwest::Client, server: &str, token: &str) -> Result<Self> {
+        let response = client
+            .get(url::build_http_url(server, "/api/chat/events"))
+            .query(&[("token", token)])
+            .send()
+            .await?;
+        check_status(response.status())?;
+        Ok(Self {
+            response,
+            buf: Vec::new(),
+            data: String::new(),
+        })
+    }
+
+    /// The next chat message, or None once the server closes the stream.
+    async fn next(&mut self) -> Result<Option<ChatMessage>> {
+        loop {
+            while let Some(end) = self.buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buf.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim_end_matches(['\r', '\n']);
+
+                if line.is_empty() {
+                    // A blank line ends the event
+                    let data = std::mem::take(&mut self.data);
+                    if let Ok(message) = serde_json::from_str::<ChatMessage>(&data) {
+                        return Ok(Some(message));
+                    }
+                } else if let Some(data) = line.strip_prefix("data:") {
+                    if !self.data.is_empty() {
+                        self.data.push('\n');
+                    }
+                    self.data.push_str(data.strip_prefix(' ').unwrap_or(data));
+                }
+            }
+            match self.response.chunk().await? {
+                Some(chunk) => self.buf.extend_from_slice(&chunk),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+fn check_status(status: reqwest::StatusCode) -> Result<()> {
+    match status {
+        s if s.is_success() => Ok(()),
+        reqwest::StatusCode::UNAUTHORIZED => bail!("the token is invalid or has expired"),
+        reqwest::StatusCode::SERVICE_UNAVAILABLE => bail!("no listener is connected for this token"),
+        s => bail!("server returned {}", s),
+    }
+}
+
+fn required_token(token: Option<&str>) -> Result<&str> {
+    token.ok_or_else(|| anyhow!("--token is required for chat commands"))
+}
+
+/// Post a message and print the reply until the turn ends.
+pub async fn send(
+    server: &str,
+    token: Option<&str>,
+    app_root: &str,
+    session: Option<&str>,
+    message: &str,
+    wait: bool,
+    format: Format,
+) -> Result<()> {
+    let token = required_token(token)?;
+    // The listener compares canonical roots, so send one when the app is local
+    let app_root = std::fs::canonicalize(app_root)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| app_root.to_string());
+
+    let client = reqwest::Client::new();
+    // Subscribe first so the start of the reply cannot be missed
+    let mut events = if wait {
+        Some(EventStream::connect(&client, server, token).await?)
+    } else {
+        None
+    };
+
+    let response = client
+        .post(url::build_http_url(server, "/api/chat/input"))
+        .json(&json!({
+            "token": token,
+            "chat_session_id": session,
+            "app_root": app_root,
+            "content": message,
+        }))
+        .send()
+        .await?;
+    // The listener answers with the session the message went to, or why it was refused
+    let status = response.status();
+    let reply: Option<ChatMessage> = response.json().await.ok();
+    let target = match reply {
+        Some(ChatMessage::ChatAccepted { chat_session_id, .. }) => chat_session_id,
+        Some(ChatMessage::RequestFailed { error, .. }) => bail!("{}", error),
+        _ => {
+            check_status(status)?;
+            bail!("unexpected reply from the server")
+        }
+    };
+
+    let Some(events) = &mut events else {
+        if let Format::Json = format {
+            println!("{}", json!({ "chat_session_id": target }));
+        } else {
+            println!("{}", target);
+        }
+        return Ok(());
+    };
+
+    while let Some(event) = events.next().await? {
+        if event.event_session_id() != Some(target.as_str()) {
+            continue;
+        }
+        if let ChatMessage::ChatError { error, .. } = &event {
+            // In text mode the error is reported once, as the command's error
+            if let Format::Json = format {
+                print_event(&event, format, false);
+            }
+            bail!("{}", error);
+        }
+        print_event(&event, format, false);
+        if let ChatMessage::ChatDone { .. } = event {
+            return Ok(());
+        }
+    }
+    bail!("the server closed the event stream")
+}
+
+/// Print chat events as they arrive, for one session or all of them.
+pub async fn follow(server: &str, token: Option<&str>, session: Option<&str>, format: Format) -> Result<()> {
+    let token = required_token(token)?;
+    let client = reqwest::Client::new();
+    let mut events = EventStream::connect(&client, server, token).await?;
+
+    while let Some(event) = events.next().await? {
+        let Some(id) = event.event_session_id() else {
+            continue;
+        };
+        if session.is_some_and(|s| s != id) {
+            continue;
+        }
+        print_event(&event, format, session.is_none());
+    }
+    bail!("the server closed the event stream")
+}
+
+fn print_event(event: &ChatMessage, format: Format, prefix_session: bool) {
+    if let Format::Json = format {
+        if let Ok(line) = serde_json::to_string(event) {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    let Some(id) = event.event_session_id() else {
+        return;
+    };
+    // Interleaved sessions are told apart by a short id
+    let prefix = if prefix_session && !id.is_empty() {
+        format!("[{}] ", id.chars().take(8).collect::<String>())
+    } else {
+        String::new()
+    };
+    let print = |text: &str| {
+        for line in text.lines() {
+            println!("{}{}", prefix, line);
+        }
+    };
+
+    match event {
+        ChatMessage::ChatSessionCreated {
+            chat_session_id,
+            app_root,
+            name,
+            ..
+        } => print(&format!("── session {} \"{}\" in {}", chat_session_id, name, app_root)),
+        ChatMessage::ChatOutput { content, .. } => {
+            for text in render_output(content) {
+                print(&text);
+            }
+        }
+        ChatMessage::ChatDiagnostic { content, .. } => eprintln!("{}{}", prefix, content),
+        ChatMessage::PermissionRequest { tool_name, .. } => {
+            print(&format!("? {} is waiting for permission in the browser", tool_name))
+        }
+        ChatMessage::TurnChanges { files, .. } => {
+            print(&format!("── {} file(s) changed", files.len()))
+        }
+        ChatMessage::ChatDone { .. } => print("── done"),
+        ChatMessage::ChatError { error, .. } => eprintln!("{}error: {}", prefix, error),
+        _ => {}
+    }
+}
+
+/// Readable text for one line of claude's stream-json output: assistant text
+/// as is and one line per tool call.
+fn render_output(line: &str) -> Vec<String> {
+    let Ok(value) = serde_json::from_str::<Value>(line) else {
+        return vec![line.to_string()];
+    };
+    if value["type"] != "assistant" {
+        return Vec::new();
+    }
+    let Some(blocks) = value["message"]["content"].as_array() else {
+        return Vec::new();
+    };
+    blocks
+        .iter()
+        .filter_map(|block| match block["type"].as_str()? {
+            "text" => block["text"].as_str().map(str::to_string),
+            "tool_use" => {
+                let name = block["name"].as_str().unwrap_or("tool");
+                Some(format!("→ {} {}", name, tool_summary(&block["input"])))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+const MAX_SUMMARY_CHARS: usize = 100;
+
+/// The most telling argument of a tool call, shortened to one line.
+fn tool_summary(input: &Value) -> String {
+    let keys = ["command", "file_path", "path", "pattern", "url", "description"];
+    let summary = keys
+        .iter()
+        .find_map(|key| input[key].as_str().map(str::to_string))
+        .unwrap_or_else(|| input.to_string());
+    let summary = summary.lines().next().unwrap_or_default();
+    match summary.char_indices().nth(MAX_SUMMARY_CHARS) {
+        Some((end, _)) => format!("{}…", &summary[..end]),
+        None => summary.to_string(),
+    }
+}
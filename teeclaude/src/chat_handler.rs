@@ -1,107 +1,407 @@
-use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::time::Instant;
 
-use teeclaude_common::ChatMessage;
+use teeclaude_common::{ChatMessage, ChatOptions, ErrorReason, UiCommand, UsageInfo};
 
-use crate::config::{ChatSession, Config};
+use crate::agent::{self, AgentEvent, CancelHandle, TurnError, TurnSpec};
+use crate::apps;
+use crate::attachments::{Attachment, AttachmentStore};
+use crate::config::{self, ChatSession, ConfigHandle, PermissionMode};
+use crate::git;
+use crate::instructions;
+use crate::listener;
+use crate::permission::PermissionBroker;
+use crate::ui_call;
+use crate::worktree;
 
-fn apply_allowed_tools(cmd: &mut tokio::process::Command, _config: &Config) {
-    // TODO: make configurable from UI, for now allow all tools
-    cmd.arg("--dangerously-skip-permissions");
+const MAX_MODEL_LEN: usize = 100;
+const MAX_TURNS_LIMIT: u32 = 500;
+const MAX_SYSTEM_PROMPT_LEN: usize = 32 * 1024;
+const MAX_ADD_DIRS: usize = 16;
+
+fn validate_options(options: &ChatOptions) -> Result<(), String> {
+    if let Some(model) = &options.model {
+        let valid_chars = model
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-._:[]".contains(c));
+        if model.is_empty() || model.len() > MAX_MODEL_LEN || !valid_chars {
+            return Err(format!("invalid model name: {:?}", model));
+        }
+    }
+    if let Some(max_turns) = options.max_turns {
+        if max_turns == 0 || max_turns > MAX_TURNS_LIMIT {
+            return Err(format!("max_turns must be between 1 and {}", MAX_TURNS_LIMIT));
+        }
+    }
+    if let Some(prompt) = &options.append_system_prompt {
+        if prompt.len() > MAX_SYSTEM_PROMPT_LEN {
+            return Err(format!(
+                "append_system_prompt exceeds {} bytes",
+                MAX_SYSTEM_PROMPT_LEN
+            ));
+        }
+    }
+    if options.add_dirs.len() > MAX_ADD_DIRS {
+        return Err(format!("at most {} add_dirs are allowed", MAX_ADD_DIRS));
+    }
+    for dir in &options.add_dirs {
+        let path = std::path::Path::new(dir);
+        if !path.is_absolute() || !path.is_dir() {
+            return Err(format!("add_dirs entry is not an existing absolute directory: {}", dir));
+        }
+    }
+    Ok(())
 }
 
-const CLAUDE_MD_TEMPLATE: &str = r#"# TeeClaude
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
 
-## ui_call 协议
+/// Cancel handles of the turns in progress, keyed by chat session id. A
+/// session is reserved (None) before its agent has started.
+pub type RunningTurns = Arc<Mutex<HashMap<String, Option<CancelHandle>>>>;
 
-当你需要通知 UI 刷新时，在输出中使用 `<ui_call>` 标签：
+/// Reservation of a session in RunningTurns, released on drop.
+pub struct TurnSlot {
+    turns: RunningTurns,
+    session_id: String,
+}
 
-```
-<ui_call>["refresh_apps"]</ui_call>
-```
+impl TurnSlot {
+    pub fn reserve(turns: &RunningTurns, session_id: &str) -> Option<Self> {
+        let mut running = turns.lock().unwrap();
+        if running.contains_key(session_id) {
+            return None;
+        }
+        running.insert(session_id.to_string(), None);
+        Some(Self {
+            turns: turns.clone(),
+            session_id: session_id.to_string(),
+        })
+    }
 
-支持的命令：
-- `refresh_apps` — 重新加载 `.teeclaude.json` 并刷新 UI 的 app 列表
+    fn started(&self, cancel: CancelHandle) {
+        self.turns
+            .lock()
+            .unwrap()
+            .insert(self.session_id.clone(), Some(cancel));
+    }
+}
 
-注意：`<ui_call>` 行不会显示在 UI 中，仅作为内部通信协议。
+impl Drop for TurnSlot {
+    fn drop(&mut self) {
+        self.turns.lock().unwrap().remove(&self.session_id);
+    }
+}
 
-## /init-project skill
+/// Stop the turn running in `session_id`; false when there is none.
+pub fn cancel_turn(turns: &RunningTurns, session_id: &str) -> bool {
+    match turns.lock().unwrap().get(session_id) {
+        Some(Some(cancel)) => {
+            cancel.cancel();
+            true
+        }
+        _ => false,
+    }
+}
 
-当用户想要初始化一个新项目时：
+/// Cancel every running turn and wait up to `timeout` for them to wind down.
+pub async fn stop_all(turns: &RunningTurns, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let running = {
+            let turns = turns.lock().unwrap();
+            for cancel in turns.values().flatten() {
+                cancel.cancel();
+            }
+            turns.len()
+        };
+        if running == 0 || Instant::now() >= deadline {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
 
-1. 引导用户提供 GitHub 仓库地址
-2. 使用 `git clone <repo_url>` 将项目 clone 到当前 root 目录下
-3. 编辑 `.teeclaude.json`，在 `apps` 数组中添加新 app 条目：
-   ```json
-   { "root": "<absolute_path_to_cloned_repo>", "sessions": [] }
-   ```
-4. 输出 `<ui_call>["refresh_apps"]</ui_call>` 通知 UI 刷新
-"#;
+pub struct ChatTurn {
+    pub chat_session_id: String,
+    /// The session is created by this turn
+    pub new_session: bool,
+    pub app_root: String,
+    pub content: String,
+    pub options: Option<ChatOptions>,
+    pub attachments: Vec<String>,
+    /// Overrides the configured permission mode for this turn
+    pub permission_mode: Option<PermissionMode>,
+}
 
-pub fn ensure_claude_md(root: &str) {
-    let path = std::path::Path::new(root).join("CLAUDE.md");
-    if !path.exists() {
-        let _ = std::fs::write(&path, CLAUDE_MD_TEMPLATE);
+fn build_prompt(content: &str, attachments: &[Attachment]) -> String {
+    if attachments.is_empty() {
+        return content.to_string();
     }
+    let mut prompt = format!("{}\n\nAttached files:\n", content);
+    for attachment in attachments {
+        prompt.push_str(&format!("- {}", attachment.path.display()));
+        if attachment.image_type.is_some() {
+            prompt.push_str(" (image)");
+        }
+        prompt.push('\n');
+    }
+    prompt
 }
 
-fn check_result_event(line: &str) -> Option<bool> {
-    let v: serde_json::Value = serde_json::from_str(line).ok()?;
-    if v.get("type")?.as_str()? != "result" {
-        return None;
+async fn report_turn_changes(
+    out_tx: &mpsc::Sender<ChatMessage>,
+    session_id: &str,
+    app_root: &str,
+    before: &git::Snapshot,
+) {
+    match git::changes_since(before).await {
+        Ok(mut files) => {
+            // The listener itself records sessions in the config file during the turn
+            files.retain(|f| {
+                std::path::Path::new(&f.path).file_name() != Some(config::CONFIG_FILE.as_ref())
+            });
+            let _ = out_tx
+                .send(ChatMessage::TurnChanges {
+                    chat_session_id: session_id.to_string(),
+                    app_root: app_root.to_string(),
+                    base: before.base().to_string(),
+                    files,
+                    timestamp: chrono::Utc::now(),
+                })
+                .await;
+        }
+        Err(e) => eprintln!("[chat] failed to collect git changes: session={}, {}", session_id, e),
+    }
+}
+
+async fn dispatch_ui_call(
+    config: &ConfigHandle,
+    out_tx: &mpsc::Sender<ChatMessage>,
+    session_id: &str,
+    call: &str,
+) {
+    let command = match ui_call::parse(call) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("[chat] ignoring ui_call: session={}, {}", session_id, e);
+            return;
+        }
+    };
+    eprintln!("[chat] ui_call: session={}, {:?}", session_id, command);
+
+    if let UiCommand::RefreshApps = command {
+        if let Err(e) = config.reload().await {
+            eprintln!("[chat] failed to reload config: {}", e);
+        }
+        listener::send_listener_ready(config, out_tx).await;
     }
-    v.get("is_error")?.as_bool()
+    let _ = out_tx
+        .send(ChatMessage::UiCommand {
+            chat_session_id: session_id.to_string(),
+            command,
+            timestamp: chrono::Utc::now(),
+        })
+        .await;
 }
 
 pub async fn handle_chat_input(
-    config: &mut Config,
+    config: &ConfigHandle,
     out_tx: &mpsc::Sender<ChatMessage>,
-    chat_session_id: Option<String>,
-    app_root: &str,
-    content: &str,
+    permissions: &PermissionBroker,
+    attachments: &AttachmentStore,
+    running: &RunningTurns,
+    turn: ChatTurn,
 ) {
-    let (session_id, is_new) = match chat_session_id {
-        Some(id) => (id, false),
-        None => {
-            let id = uuid::Uuid::new_v4().to_string();
-            (id, true)
+    let app_root = turn.app_root.as_str();
+    let content = turn.content.as_str();
+
+    let session_id = turn.chat_session_id;
+    let is_new = turn.new_session;
+
+    let Some(slot) = TurnSlot::reserve(running, &session_id) else {
+        eprintln!("[chat] refused turn: session={}, already running", session_id);
+        let _ = out_tx
+            .send(ChatMessage::chat_error(
+                &session_id,
+                "a turn is already running in this session",
+            ))
+            .await;
+        return;
+    };
+
+    let snapshot = match config.snapshot().await {
+        Ok(c) => c,
+        Err(e) => {
+            let _ = out_tx
+                .send(ChatMessage::chat_error(&session_id, e.to_string()))
+                .await;
+            return;
+        }
+    };
+
+    if let Err(reason) =
+        snapshot.check_budget(app_root, (!is_new).then_some(session_id.as_str()))
+    {
+        eprintln!("[chat] refused turn: session={}, {}", session_id, reason);
+        let _ = out_tx
+            .send(ChatMessage::chat_error_with_reason(
+                &session_id,
+                format!("budget exceeded: {}", reason),
+                Some(ErrorReason::BudgetExceeded),
+            ))
+            .await;
+        return;
+    }
+
+    let mut options = turn
+        .options
+        .unwrap_or_default()
+        .merged_over(&snapshot.app_defaults(app_root));
+    if let Err(e) = validate_options(&options) {
+        eprintln!("[chat] rejected options: session={}, {}", session_id, e);
+        let _ = out_tx.send(ChatMessage::chat_error(&session_id, e)).await;
+        return;
+    }
+    // Extra directories get the same confinement as app roots
+    for dir in &mut options.add_dirs {
+        match apps::confine(config, Path::new(dir)).await {
+            Ok(resolved) => *dir = resolved.to_string_lossy().to_string(),
+            Err(e) => {
+                eprintln!("[chat] rejected options: session={}, {}", session_id, e);
+                let msg = format!("add_dirs entry rejected: {}", e);
+                let _ = out_tx.send(ChatMessage::chat_error(&session_id, msg)).await;
+                return;
+            }
+        }
+    }
+
+    let attached = match attachments
+        .take(&turn.attachments, app_root, &session_id)
+        .await
+    {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("[chat] attachment error: session={}, {}", session_id, e);
+            let _ = out_tx
+                .send(ChatMessage::chat_error(&session_id, e.to_string()))
+                .await;
+            return;
         }
     };
 
-    let mut cmd = tokio::process::Command::new("claude");
-    cmd.arg("-p").arg(content);
-    cmd.arg("--output-format").arg("stream-json");
-    cmd.arg("--verbose");
+    let instructions = match instructions::load(&snapshot) {
+        Ok(i) => i,
+        Err(e) => {
+            eprintln!("[chat] {}", e);
+            let _ = out_tx
+                .send(ChatMessage::chat_error(&session_id, e.to_string()))
+                .await;
+            return;
+        }
+    };
 
-    if is_new {
-        cmd.arg("--session-id").arg(&session_id);
+    // Isolated sessions run in their own worktree, created with the session
+    let (cwd, worktree) = if is_new && options.isolated == Some(true) {
+        match worktree::create(app_root, &session_id).await {
+            Ok(w) => (w.path.clone(), Some(w)),
+            Err(e) => {
+                eprintln!("[chat] failed to create worktree: session={}, {}", session_id, e);
+                let _ = out_tx
+                    .send(ChatMessage::chat_error(&session_id, e.to_string()))
+                    .await;
+                return;
+            }
+        }
     } else {
-        cmd.arg("-r").arg(&session_id);
-    }
+        let cwd = snapshot
+            .session_cwd(&session_id)
+            .unwrap_or_else(|| app_root.to_string());
+        if !Path::new(&cwd).is_dir() {
+            let _ = out_tx
+                .send(ChatMessage::chat_error(
+                    &session_id,
+                    "the session's worktree has been removed",
+                ))
+                .await;
+            return;
+        }
+        (cwd, None)
+    };
 
-    apply_allowed_tools(&mut cmd, config);
+    let limits = snapshot.app_limits(app_root);
+    let backend = agent::backend_for(&snapshot.backend, permissions);
+    let spec = TurnSpec {
+        session_id: session_id.clone(),
+        app_root: app_root.to_string(),
+        cwd: cwd.clone(),
+        prompt: build_prompt(content, &attached),
+        attachments: attached,
+        instructions,
+        options,
+        permission_mode: turn.permission_mode.unwrap_or(snapshot.permission_mode),
+        allowed_tools: snapshot.allowed_tools.clone(),
+        limits,
+        sandbox: snapshot.app_sandbox(app_root),
+        config_path: snapshot.config_path.clone(),
+    };
 
-    cmd.current_dir(app_root);
-    cmd.stdin(Stdio::null());
-    cmd.stdout(Stdio::piped());
-    cmd.stderr(Stdio::piped());
+    let pending_fork = snapshot.pending_fork(&session_id);
+    let git_before = git::snapshot(&cwd).await;
 
     eprintln!(
-        "[chat] spawning claude: session={}, app_root={}, new={}",
-        session_id, app_root, is_new
+        "[chat] spawning {}: session={}, cwd={}, new={}",
+        backend.name(),
+        session_id,
+        cwd,
+        is_new
     );
 
-    let mut child = match cmd.spawn() {
-        Ok(c) => c,
+    let started = if is_new {
+        backend.start_turn(spec)
+    } else if let Some(parent_id) = &pending_fork {
+        backend.fork_session(spec, parent_id)
+    } else {
+        backend.resume_session(spec)
+    };
+    let mut running_turn = match started {
+        Ok(t) => t,
         Err(e) => {
-            eprintln!("[chat] failed to spawn claude: {}", e);
+            eprintln!("[chat] failed to spawn {}: {}", backend.name(), e);
+            if let Some(w) = &worktree {
+                if let Err(e) = worktree::discard(app_root, w).await {
+                    eprintln!("[chat] failed to remove worktree {}: {}", w.path, e);
+                }
+            }
             let _ = out_tx
                 .send(ChatMessage::chat_error(&session_id, e.to_string()))
                 .await;
             return;
         }
     };
+    slot.started(running_turn.cancel_handle());
+
+    if pending_fork.is_some() {
+        let id = session_id.clone();
+        let cleared = config
+            .update(move |c| {
+                if let Some(session) = c.session_mut(&id) {
+                    session.pending_fork = None;
+                }
+            })
+            .await;
+        if let Err(e) = cleared {
+            eprintln!("[chat] failed to record fork: {}", e);
+        }
+    }
 
     if is_new {
         let name = content.chars().take(50).collect::<String>();
@@ -110,68 +410,151 @@ pub async fn handle_chat_input(
             name: name.clone(),
             created_at: chrono::Utc::now(),
             last_active: chrono::Utc::now(),
+            archived: false,
+            usage: UsageInfo::default(),
+            worktree: worktree.clone(),
+            forked_from: None,
+            pending_fork: None,
         };
-        let _ = config.add_session(app_root, session);
+        let app_root_owned = app_root.to_string();
+        if let Err(e) = config
+            .update(move |c| c.add_session(&app_root_owned, session))
+            .await
+        {
+            eprintln!("[chat] failed to record session: {}", e);
+        }
 
         let _ = out_tx
             .send(ChatMessage::chat_session_created(
                 &session_id,
                 app_root,
                 &name,
+                worktree.map(|w| w.branch),
+                None,
             ))
             .await;
+        listener::send_listener_ready(config, out_tx).await;
     }
 
-    // Stream stdout: forward raw stream-json lines to UI
     let mut got_result_error = false;
+    let mut turn_usage = None;
 
-    if let Some(stdout) = child.stdout.take() {
-        let reader = BufReader::new(stdout);
-        let mut lines = reader.lines();
+    let cancel = running_turn.cancel_handle();
+    let max_idle = limits.max_idle_secs.map(Duration::from_secs);
+    let mut turn_deadline = limits
+        .max_turn_secs
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+    let mut stopped: Option<TurnError> = None;
 
-        while let Ok(Some(line)) = lines.next_line().await {
-            if let Some(is_error) = check_result_event(&line) {
+    loop {
+        // Once stopped, keep draining so the backend can wind down
+        let idle_deadline = max_idle
+            .filter(|_| stopped.is_none())
+            .map(|d| Instant::now() + d);
+        let event = tokio::select! {
+            event = running_turn.events.recv() => event,
+            _ = sleep_until(turn_deadline) => {
+                let secs = limits.max_turn_secs.unwrap_or_default();
+                stopped = Some(TurnError::with_reason(
+                    format!("turn exceeded the {}s time limit", secs),
+                    ErrorReason::TurnTimeout,
+                ));
+                turn_deadline = None;
+                cancel.cancel();
+                continue;
+            }
+            _ = sleep_until(idle_deadline) => {
+                let secs = limits.max_idle_secs.unwrap_or_default();
+                stopped = Some(TurnError::with_reason(
+                    format!("no output for {}s", secs),
+                    ErrorReason::IdleTimeout,
+                ));
+                turn_deadline = None;
+                cancel.cancel();
+                continue;
+            }
+        };
+        let Some(event) = event else {
+            break;
+        };
+        match event {
+            AgentEvent::Output(line) => {
+                let (line, calls) = ui_call::extract(&line);
+                let _ = out_tx
+                    .send(ChatMessage::chat_output(&session_id, line))
+                    .await;
+                for call in calls {
+                    dispatch_ui_call(config, out_tx, &session_id, &call).await;
+                }
+            }
+            AgentEvent::Diagnostic(line) => {
+                let _ = out_tx
+                    .send(ChatMessage::ChatDiagnostic {
+                        chat_session_id: session_id.clone(),
+                        content: line,
+                        timestamp: chrono::Utc::now(),
+                    })
+                    .await;
+            }
+            AgentEvent::Result { is_error, usage } => {
                 got_result_error = is_error;
+                turn_usage = Some(usage);
             }
-            let _ = out_tx
-                .send(ChatMessage::chat_output(&session_id, line))
-                .await;
         }
     }
 
-    match child.wait().await {
-        Ok(status) if status.success() && !got_result_error => {
-            eprintln!("[chat] claude exited successfully: session={}", session_id);
-            let _ = config.update_session_activity(app_root, &session_id);
-            let _ = out_tx.send(ChatMessage::chat_done(&session_id)).await;
+    let outcome = running_turn.wait().await;
+    drop(slot);
+
+    if let Some(usage) = turn_usage {
+        let (root, id) = (app_root.to_string(), session_id.clone());
+        match config
+            .update(move |c| c.record_usage(&root, &id, &usage))
+            .await
+        {
+            Ok(()) => listener::send_listener_ready(config, out_tx).await,
+            Err(e) => eprintln!("[chat] failed to record usage: session={}, {}", session_id, e),
         }
-        Ok(status) => {
-            let stderr_output = if let Some(stderr) = child.stderr.take() {
-                let mut buf = String::new();
-                let mut reader = BufReader::new(stderr);
-                let _ = tokio::io::AsyncReadExt::read_to_string(&mut reader, &mut buf).await;
-                buf
-            } else {
-                String::new()
-            };
-            let msg = if stderr_output.is_empty() {
-                format!("claude exited with status {}", status)
-            } else {
-                format!(
-                    "claude exited with status {}: {}",
-                    status,
-                    stderr_output.trim()
-                )
-            };
-            eprintln!("[chat] claude error: session={}, {}", session_id, msg);
-            let _ = out_tx
-                .send(ChatMessage::chat_error(&session_id, msg))
-                .await;
+    }
+
+    if let Some(before) = git_before {
+        report_turn_changes(out_tx, &session_id, app_root, &before).await;
+    }
+
+    let outcome = match (stopped, outcome) {
+        (Some(stopped), _) => Err(stopped),
+        (None, Ok(())) if got_result_error => Err(TurnError::new(format!(
+            "{} reported an error",
+            backend.name()
+        ))),
+        (None, other) => other,
+    };
+
+    match outcome {
+        Ok(()) => {
+            eprintln!("[chat] {} finished: session={}", backend.name(), session_id);
+            let (root, id) = (app_root.to_string(), session_id.clone());
+            if let Err(e) = config
+                .update(move |c| c.update_session_activity(&root, &id))
+                .await
+            {
+                eprintln!("[chat] failed to update session activity: {}", e);
+            }
+            let _ = out_tx.send(ChatMessage::chat_done(&session_id)).await;
         }
         Err(e) => {
-            eprintln!("[chat] wait error: session={}, {}", session_id, e);
+            eprintln!(
+                "[chat] {} error: session={}, {}",
+                backend.name(),
+                session_id,
+                e.message
+            );
             let _ = out_tx
-                .send(ChatMessage::chat_error(&session_id, e.to_string()))
+                .send(ChatMessage::chat_error_with_reason(
+                    &session_id,
+                    e.message,
+                    e.reason,
+                ))
                 .await;
         }
     }
@@ -1,11 +1,17 @@
-use anyhow::Result;
-use chrono::{DateTime, Utc};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::sync::{mpsc, oneshot};
 
-use teeclaude_common::AppInfo;
+use teeclaude_common::{AppInfo, ChatOptions, ChatSessionInfo, UsageInfo};
 
-const CONFIG_FILE: &str = ".teeclaude.json";
+use crate::instructions::InstructionsLang;
+use crate::schedule::Cron;
+
+pub const CONFIG_FILE: &str = ".teeclaude.json";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -13,9 +19,83 @@ pub struct Config {
     pub config_path: PathBuf,
     #[serde(default = "default_allowed_tools")]
     pub allowed_tools: Vec<String>,
+    #[serde(default)]
+    pub permission_mode: PermissionMode,
+    #[serde(default = "default_permission_timeout_secs")]
+    pub permission_timeout_secs: u64,
+    #[serde(default = "default_max_attachment_bytes")]
+    pub max_attachment_bytes: u64,
+    #[serde(default, skip_serializing_if = "BackendConfig::is_default")]
+    pub backend: BackendConfig,
+    /// File with custom agent instructions, replacing the built-in template
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions_path: Option<PathBuf>,
+    #[serde(default)]
+    pub instructions_lang: InstructionsLang,
+    /// Where apps added by git URL are cloned; defaults to the listener's root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace_dir: Option<PathBuf>,
+    /// Directories apps must live under; defaults to the listener's root
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_bases: Vec<PathBuf>,
+    /// Prompts the listener runs on its own, each in a new session
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<Schedule>,
     pub apps: Vec<App>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    /// When to run, as a cron expression in local time
+    pub cron: Cron,
+    pub app_root: String,
+    pub prompt: String,
+    /// Overrides the global `permission_mode` for these runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission_mode: Option<PermissionMode>,
+}
+
+/// Which agent runs chat turns.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BackendConfig {
+    /// The `claude` CLI
+    #[default]
+    Claude,
+    /// Replays `script` (stream-json lines) or echoes the prompt; for testing
+    Echo {
+        #[serde(default)]
+        script: Vec<String>,
+        #[serde(default)]
+        delay_ms: u64,
+    },
+}
+
+impl BackendConfig {
+    pub fn is_default(&self) -> bool {
+        *self == BackendConfig::Claude
+    }
+}
+
+/// How tool permissions are granted to claude.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionMode {
+    /// Skip all permission checks
+    #[default]
+    Skip,
+    /// Allow `allowed_tools` and ask the browser for everything else
+    Prompt,
+}
+
+fn default_permission_timeout_secs() -> u64 {
+    120
+}
+
+fn default_max_attachment_bytes() -> u64 {
+    20 * 1024 * 1024
+}
+
 fn default_allowed_tools() -> Vec<String> {
     [
         "Edit",
@@ -36,6 +116,102 @@ fn default_allowed_tools() -> Vec<String> {
 pub struct App {
     pub root: String,
     pub sessions: Vec<ChatSession>,
+    /// Claude options applied to every turn unless the message overrides them
+    #[serde(default, skip_serializing_if = "ChatOptions::is_empty")]
+    pub defaults: ChatOptions,
+    #[serde(default, skip_serializing_if = "Budget::is_empty")]
+    pub budget: Budget,
+    #[serde(default, skip_serializing_if = "Limits::is_empty")]
+    pub limits: Limits,
+    #[serde(default, skip_serializing_if = "Sandbox::is_default")]
+    pub sandbox: Sandbox,
+    #[serde(default, skip_serializing_if = "UsageInfo::is_empty")]
+    pub usage: UsageInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_usage: Option<DailyUsage>,
+}
+
+/// Spending limits in USD; turns are refused once a limit is reached.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Budget {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_usd: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_usd: Option<f64>,
+}
+
+impl Budget {
+    pub fn is_empty(&self) -> bool {
+        self.daily_usd.is_none() && self.session_usd.is_none()
+    }
+}
+
+/// Limits on a single turn's agent process.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Limits {
+    /// Wall-clock limit for a whole turn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turn_secs: Option<u64>,
+    /// Longest allowed silence between output lines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_idle_secs: Option<u64>,
+    /// RLIMIT_CPU for the agent process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cpu_secs: Option<u64>,
+    /// RLIMIT_AS (address space) for the agent process; only a rough limit
+    /// for Node, which reserves far more virtual memory than it uses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_mb: Option<u64>,
+    /// Scheduling priority (niceness) of the agent process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+}
+
+impl Limits {
+    pub fn is_empty(&self) -> bool {
+        self.max_turn_secs.is_none()
+            && self.max_idle_secs.is_none()
+            && self.max_cpu_secs.is_none()
+            && self.max_memory_mb.is_none()
+            && self.nice.is_none()
+    }
+}
+
+/// Run claude under bubblewrap: the app root is writable, the rest of the
+/// filesystem read-only and `$HOME` hidden apart from claude's own state.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sandbox {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Extra paths mounted read-write
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub read_write: Vec<PathBuf>,
+    /// Extra paths mounted read-only, e.g. toolchains installed under `$HOME`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub read_only: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    pub fn is_default(&self) -> bool {
+        *self == Sandbox::default()
+    }
+}
+
+/// Usage for a single local calendar day.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyUsage {
+    pub date: NaiveDate,
+    pub usage: UsageInfo,
+}
+
+impl App {
+    fn usage_today(&self) -> UsageInfo {
+        let today = Local::now().date_naive();
+        match &self.daily_usage {
+            Some(d) if d.date == today => d.usage.clone(),
+            _ => UsageInfo::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,6 +220,26 @@ pub struct ChatSession {
     pub name: String,
     pub created_at: DateTime<Utc>,
     pub last_active: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    #[serde(default, skip_serializing_if = "UsageInfo::is_empty")]
+    pub usage: UsageInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<Worktree>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_from: Option<String>,
+    /// Claude session to fork from when the first turn starts; claude only
+    /// creates a fork together with a prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_fork: Option<String>,
+}
+
+/// Git worktree of an isolated session. It is kept after merging or
+/// discarding, since claude's transcript is stored under its path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Worktree {
+    pub path: String,
+    pub branch: String,
 }
 
 impl Config {
@@ -58,6 +254,15 @@ impl Config {
             let config = Config {
                 config_path,
                 allowed_tools: default_allowed_tools(),
+                permission_mode: PermissionMode::default(),
+                permission_timeout_secs: default_permission_timeout_secs(),
+                max_attachment_bytes: default_max_attachment_bytes(),
+                backend: BackendConfig::default(),
+                instructions_path: None,
+                instructions_lang: InstructionsLang::default(),
+                workspace_dir: None,
+                allowed_bases: vec![],
+                schedules: vec![],
                 apps: vec![],
             };
             config.save()?;
@@ -65,9 +270,24 @@ impl Config {
         }
     }
 
+    /// Write via a temp file and rename so a crash never leaves a truncated config.
     pub fn save(&self) -> Result<()> {
         let content = serde_json::to_string_pretty(self)?;
-        std::fs::write(&self.config_path, content)?;
+        let tmp_path = self.config_path.with_extension("json.tmp");
+        {
+            let mut file = std::fs::File::create(&tmp_path)?;
+            file.write_all(content.as_bytes())?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.config_path)?;
+        Ok(())
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        let content = std::fs::read_to_string(&self.config_path)?;
+        let mut fresh: Config = serde_json::from_str(&content)?;
+        fresh.config_path = std::mem::take(&mut self.config_path);
+        *self = fresh;
         Ok(())
     }
 
@@ -76,34 +296,366 @@ impl Config {
             self.apps.push(App {
                 root: root.to_string(),
                 sessions: vec![],
+                defaults: ChatOptions::default(),
+                budget: Budget::default(),
+                limits: Limits::default(),
+                sandbox: Sandbox::default(),
+                usage: UsageInfo::default(),
+                daily_usage: None,
             });
         }
         self.apps.iter_mut().find(|a| a.root == root).unwrap()
     }
 
-    pub fn add_session(&mut self, app_root: &str, session: ChatSession) -> Result<()> {
+    /// Unregister an app; false when it was not registered.
+    pub fn remove_app(&mut self, root: &str) -> bool {
+        let before = self.apps.len();
+        self.apps.retain(|a| a.root != root);
+        self.apps.len() != before
+    }
+
+    /// Directory holding the config file, i.e. the root the listener started in.
+    fn listener_root(&self) -> PathBuf {
+        self.config_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    }
+
+    pub fn workspace_dir(&self) -> PathBuf {
+        self.workspace_dir
+            .clone()
+            .unwrap_or_else(|| self.listener_root())
+    }
+
+    pub fn allowed_bases(&self) -> Vec<PathBuf> {
+        if self.allowed_bases.is_empty() {
+            vec![self.listener_root()]
+        } else {
+            self.allowed_bases.clone()
+        }
+    }
+
+    pub fn add_session(&mut self, app_root: &str, session: ChatSession) {
         let app = self.ensure_app(app_root);
         app.sessions.push(session);
-        self.save()
     }
 
-    pub fn update_session_activity(&mut self, app_root: &str, session_id: &str) -> Result<()> {
+    pub fn update_session_activity(&mut self, app_root: &str, session_id: &str) {
         if let Some(app) = self.apps.iter_mut().find(|a| a.root == app_root) {
             if let Some(session) = app.sessions.iter_mut().find(|s| s.id == session_id) {
                 session.last_active = Utc::now();
-                self.save()?;
             }
         }
+    }
+
+    pub fn app_defaults(&self, root: &str) -> ChatOptions {
+        self.apps
+            .iter()
+            .find(|a| a.root == root)
+            .map(|a| a.defaults.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn app_limits(&self, root: &str) -> Limits {
+        self.apps
+            .iter()
+            .find(|a| a.root == root)
+            .map(|a| a.limits)
+            .unwrap_or_default()
+    }
+
+    /// Sandbox settings for the app, None when the sandbox is off.
+    pub fn app_sandbox(&self, root: &str) -> Option<Sandbox> {
+        self.apps
+            .iter()
+            .find(|a| a.root == root)
+            .map(|a| a.sandbox.clone())
+            .filter(|s| s.enabled)
+    }
+
+    pub fn has_app(&self, root: &str) -> bool {
+        self.apps.iter().any(|a| a.root == root)
+    }
+
+    /// Add a turn's usage to the session, the app total and today's tally.
+    pub fn record_usage(&mut self, app_root: &str, session_id: &str, usage: &UsageInfo) {
+        let Some(app) = self.apps.iter_mut().find(|a| a.root == app_root) else {
+            return;
+        };
+        app.usage.add(usage);
+
+        let today = Local::now().date_naive();
+        match &mut app.daily_usage {
+            Some(d) if d.date == today => d.usage.add(usage),
+            _ => {
+                app.daily_usage = Some(DailyUsage {
+                    date: today,
+                    usage: usage.clone(),
+                })
+            }
+        }
+
+        if let Some(session) = app.sessions.iter_mut().find(|s| s.id == session_id) {
+            session.usage.add(usage);
+        }
+    }
+
+    /// Err with a user-facing reason when a new turn would exceed a budget.
+    pub fn check_budget(&self, app_root: &str, session_id: Option<&str>) -> Result<(), String> {
+        let Some(app) = self.apps.iter().find(|a| a.root == app_root) else {
+            return Ok(());
+        };
+
+        if let Some(limit) = app.budget.daily_usd {
+            let spent = app.usage_today().cost_usd;
+            if spent >= limit {
+                return Err(format!(
+                    "daily budget of ${:.2} reached (spent ${:.2} today)",
+                    limit, spent
+                ));
+            }
+        }
+
+        if let (Some(limit), Some(id)) = (app.budget.session_usd, session_id) {
+            if let Some(session) = app.sessions.iter().find(|s| s.id == id) {
+                if session.usage.cost_usd >= limit {
+                    return Err(format!(
+                        "session budget of ${:.2} reached (spent ${:.2})",
+                        limit, session.usage.cost_usd
+                    ));
+                }
+            }
+        }
+
         Ok(())
     }
 
+    /// Session a forked session still has to be created from.
+    pub fn pending_fork(&self, session_id: &str) -> Option<String> {
+        self.apps
+            .iter()
+            .flat_map(|a| a.sessions.iter())
+            .find(|s| s.id == session_id)?
+            .pending_fork
+            .clone()
+    }
+
+    /// Directory the session's turns run in: its worktree, else the app root.
+    /// Root of the app a session belongs to.
+    pub fn session_app_root(&self, session_id: &str) -> Option<String> {
+        self.apps
+            .iter()
+            .find(|a| a.sessions.iter().any(|s| s.id == session_id))
+            .map(|a| a.root.clone())
+    }
+
+    pub fn session_cwd(&self, session_id: &str) -> Option<String> {
+        self.apps.iter().find_map(|a| {
+            let session = a.sessions.iter().find(|s| s.id == session_id)?;
+            Some(match &session.worktree {
+                Some(w) => w.path.clone(),
+                None => a.root.clone(),
+            })
+        })
+    }
+
+    pub fn session_mut(&mut self, session_id: &str) -> Option<&mut ChatSession> {
+        self.apps
+            .iter_mut()
+            .flat_map(|a| a.sessions.iter_mut())
+            .find(|s| s.id == session_id)
+    }
+
+    /// Add a fork of a session to the same app, returning the app root and the
+    /// new session. Forks of isolated sessions bring their own `worktree`.
+    pub fn fork_session(
+        &mut self,
+        session_id: &str,
+        fork_id: &str,
+        worktree: Option<Worktree>,
+    ) -> Option<(String, ChatSession)> {
+        let app = self
+            .apps
+            .iter_mut()
+            .find(|a| a.sessions.iter().any(|s| s.id == session_id))?;
+        let parent = app.sessions.iter().find(|s| s.id == session_id)?;
+        let now = Utc::now();
+        let fork = ChatSession {
+            id: fork_id.to_string(),
+            name: format!("{} (fork)", parent.name),
+            created_at: now,
+            last_active: now,
+            archived: false,
+            usage: UsageInfo::default(),
+            worktree,
+            forked_from: Some(parent.id.clone()),
+            // A fork of a fork that has not run yet starts from the same point
+            pending_fork: Some(
+                parent
+                    .pending_fork
+                    .clone()
+                    .unwrap_or_else(|| parent.id.clone()),
+            ),
+        };
+        app.sessions.push(fork.clone());
+        Some((app.root.clone(), fork))
+    }
+
+    /// Remove a session, returning the root of the app it belonged to.
+    pub fn remove_session(&mut self, session_id: &str) -> Option<String> {
+        let app = self
+            .apps
+            .iter_mut()
+            .find(|a| a.sessions.iter().any(|s| s.id == session_id))?;
+        app.sessions.retain(|s| s.id != session_id);
+        Some(app.root.clone())
+    }
+
     pub fn to_app_infos(&self) -> Vec<AppInfo> {
         self.apps
             .iter()
             .map(|a| AppInfo {
                 root: a.root.clone(),
                 name: a.root.rsplit('/').next().unwrap_or(&a.root).to_string(),
+                sessions: a
+                    .sessions
+                    .iter()
+                    .map(|s| ChatSessionInfo {
+                        id: s.id.clone(),
+                        name: s.name.clone(),
+                        app_root: a.root.clone(),
+                        created_at: s.created_at,
+                        last_active: s.last_active,
+                        archived: s.archived,
+                        usage: s.usage.clone(),
+                        branch: s.worktree.as_ref().map(|w| w.branch.clone()),
+                        forked_from: s.forked_from.clone(),
+                    })
+                    .collect(),
+                usage: a.usage.clone(),
+                usage_today: a.usage_today(),
             })
             .collect()
     }
 }
+
+type ReadFn = Box<dyn FnOnce(&Config) + Send>;
+type UpdateFn = Box<dyn FnOnce(&mut Config) + Send>;
+
+enum ConfigCommand {
+    Read(ReadFn),
+    Update(UpdateFn, oneshot::Sender<Result<()>>),
+    Reload(oneshot::Sender<Result<()>>),
+}
+
+/// Handle to the task that owns `Config`.
+///
+/// All reads and mutations are serialized through a single owner, which
+/// picks up external edits to `.teeclaude.json` before running each command
+/// and saves after every update.
+#[derive(Clone)]
+pub struct ConfigHandle {
+    tx: mpsc::Sender<ConfigCommand>,
+}
+
+impl ConfigHandle {
+    pub fn spawn(config: Config) -> Self {
+        let (tx, mut rx) = mpsc::channel::<ConfigCommand>(100);
+
+        tokio::spawn(async move {
+            let mut config = config;
+            let mut stamp = file_stamp(&config.config_path);
+
+            while let Some(cmd) = rx.recv().await {
+                let current = file_stamp(&config.config_path);
+                let force = matches!(cmd, ConfigCommand::Reload(_));
+                let mut reload_result = Ok(());
+                if force || current != stamp {
+                    reload_result = config.reload();
+                    match &reload_result {
+                        Ok(()) => stamp = current,
+                        Err(e) => eprintln!("[config] failed to reload: {}", e),
+                    }
+                }
+
+                match cmd {
+                    ConfigCommand::Read(f) => f(&config),
+                    // Saving now would write the stale copy over the edit on disk
+                    ConfigCommand::Update(_, reply) if reload_result.is_err() => {
+                        let path = config.config_path.display().to_string();
+                        let _ = reply.send(reload_result.map_err(|e| {
+                            anyhow!("{} changed on disk but could not be read: {}", path, e)
+                        }));
+                    }
+                    ConfigCommand::Update(f, reply) => {
+                        f(&mut config);
+                        let result = config.save();
+                        if result.is_ok() {
+                            stamp = file_stamp(&config.config_path);
+                        }
+                        let _ = reply.send(result);
+                    }
+                    ConfigCommand::Reload(reply) => {
+                        let _ = reply.send(reload_result);
+                    }
+                }
+            }
+        });
+
+        Self { tx }
+    }
+
+    pub async fn read<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&Config) -> R + Send + 'static,
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let op: ReadFn = Box::new(move |config| {
+            let _ = reply_tx.send(f(config));
+        });
+        self.tx
+            .send(ConfigCommand::Read(op))
+            .await
+            .map_err(|_| anyhow!("config owner stopped"))?;
+        reply_rx.await.map_err(|_| anyhow!("config owner stopped"))
+    }
+
+    pub async fn update<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Config) -> R + Send + 'static,
+    {
+        let (value_tx, value_rx) = oneshot::channel();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let op: UpdateFn = Box::new(move |config| {
+            let _ = value_tx.send(f(config));
+        });
+        self.tx
+            .send(ConfigCommand::Update(op, reply_tx))
+            .await
+            .map_err(|_| anyhow!("config owner stopped"))?;
+        reply_rx.await.map_err(|_| anyhow!("config owner stopped"))??;
+        value_rx.await.map_err(|_| anyhow!("config owner stopped"))
+    }
+
+    /// Force a reload from disk, e.g. after claude edited `.teeclaude.json`.
+    pub async fn reload(&self) -> Result<()> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.tx
+            .send(ConfigCommand::Reload(reply_tx))
+            .await
+            .map_err(|_| anyhow!("config owner stopped"))?;
+        reply_rx.await.map_err(|_| anyhow!("config owner stopped"))?
+    }
+
+    pub async fn snapshot(&self) -> Result<Config> {
+        self.read(|config| config.clone()).await
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
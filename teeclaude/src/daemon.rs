@@ -2,11 +2,14 @@ use std::collections::hash_map::DefaultHasher;
 use std::fs;
 use std::hash::{Hash, Hasher};
 use std::os::unix::process::CommandExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Result};
 
+use crate::pty;
+
 pub fn start(server: &str, token: Option<&str>, root: Option<&str>) -> Result<()> {
     let id = gateway_id(token);
     let pid_file = pid_path(&id);
@@ -50,6 +53,74 @@ pub fn start(server: &str, token: Option<&str>, root: Option<&str>) -> Result<()
     Ok(())
 }
 
+/// How long `detach` waits for the background session to connect.
+const DETACH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run this invocation again in the background with `--foreground`, detached
+/// from the terminal so it outlives the launching shell. Returns once the
+/// session has connected, or fails with its last diagnostics if it exits
+/// first. Diagnostics go to a `headless-*.log` in the gateway dir, apart from
+/// the session's output in `log`.
+pub fn detach(log: Option<&Path>) -> Result<()> {
+    fs::create_dir_all(gateway_dir())?;
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let diag_file = gateway_dir().join(format!("headless-{}.log", &id[..8]));
+
+    let exe = std::env::current_exe()?;
+    let mut cmd = Command::new(&exe);
+    cmd.arg("--foreground");
+    cmd.args(std::env::args_os().skip(1));
+
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::null());
+    cmd.stderr(fs::File::create(&diag_file)?);
+
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+
+    let mut child = cmd.spawn()?;
+    let deadline = Instant::now() + DETACH_TIMEOUT;
+    loop {
+        let diagnostics = fs::read_to_string(&diag_file).unwrap_or_default();
+        if let Some(line) = diagnostics.lines().find(|l| l.starts_with(pty::HEADLESS_READY)) {
+            eprintln!("{line}");
+            eprintln!("Detached (PID {}).", child.id());
+            break;
+        }
+        if let Some(status) = child.try_wait()? {
+            // Show the error the session exited with, or else the last few lines
+            let lines: Vec<&str> = diagnostics.lines().collect();
+            let tail = match lines.iter().rposition(|l| l.starts_with("Error: ")) {
+                Some(i) => lines[i..]
+                    .iter()
+                    .take_while(|l| l.trim() != "Stack backtrace:")
+                    .copied()
+                    .collect::<Vec<_>>(),
+                None => lines[lines.len().saturating_sub(5)..].to_vec(),
+            };
+            let tail = tail.join("\n");
+            bail!(
+                "headless session failed to start ({status}):\n{tail}\nDiagnostics: {}",
+                diag_file.display()
+            );
+        }
+        if Instant::now() >= deadline {
+            eprintln!("Headless session (PID {}) is still starting.", child.id());
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    eprintln!("Diagnostics: {}", diag_file.display());
+    if let Some(path) = log {
+        eprintln!("Log: {}", path.display());
+    }
+    Ok(())
+}
+
 pub fn stop(token: Option<&str>) -> Result<()> {
     let id = gateway_id(token);
     let pid_file = pid_path(&id);
@@ -103,7 +174,7 @@ pub fn status(token: Option<&str>) -> Result<()> {
     Ok(())
 }
 
-fn gateway_dir() -> PathBuf {
+pub fn gateway_dir() -> PathBuf {
     dirs_or_home().join(".teeclaude")
 }
 
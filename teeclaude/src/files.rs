@@ -0,0 +1,174 @@
+use anyhow::{anyhow, bail, Result};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+
+use teeclaude_common::{ChatMessage, DirEntry};
+
+use crate::config::ConfigHandle;
+
+const MAX_DIR_ENTRIES: usize = 2000;
+const MAX_FILE_BYTES: u64 = 512 * 1024;
+const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+
+/// Resolve `path` (relative to `app_root`, or absolute) and make sure it stays
+/// inside that registered app root after following symlinks.
+async fn resolve(config: &ConfigHandle, app_root: &str, path: &str) -> Result<(PathBuf, PathBuf)> {
+    let root = app_root.to_string();
+    if !config.read(move |c| c.has_app(&root)).await? {
+        bail!("unknown app root: {}", app_root);
+    }
+
+    let root = tokio::fs::canonicalize(app_root).await?;
+    let requested = Path::new(path);
+    let joined = if requested.is_absolute() {
+        requested.to_path_buf()
+    } else {
+        root.join(requested)
+    };
+    let resolved = tokio::fs::canonicalize(&joined)
+        .await
+        .map_err(|e| anyhow!("{}: {}", path, e))?;
+    if !resolved.starts_with(&root) {
+        bail!("path is outside the app root: {}", path);
+    }
+    Ok((root, resolved))
+}
+
+fn display_path(root: &Path, path: &Path) -> String {
+    match path.strip_prefix(root) {
+        Ok(rel) if rel.as_os_str().is_empty() => ".".to_string(),
+        Ok(rel) => rel.to_string_lossy().to_string(),
+        Err(_) => path.to_string_lossy().to_string(),
+    }
+}
+
+async fn list_dir(config: &ConfigHandle, request_id: &str, app_root: &str, path: &str) -> Result<ChatMessage> {
+    let (root, dir) = resolve(config, app_root, path).await?;
+
+    let mut reader = tokio::fs::read_dir(&dir).await?;
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    while let Some(entry) = reader.next_entry().await? {
+        if entries.len() >= MAX_DIR_ENTRIES {
+            truncated = true;
+            break;
+        }
+        let Ok(meta) = entry.metadata().await else {
+            continue;
+        };
+        entries.push(DirEntry {
+            name: entry.file_name().to_string_lossy().to_string(),
+            is_dir: meta.is_dir(),
+            size: meta.len(),
+            modified: meta.modified().ok().map(Into::into),
+        });
+    }
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    Ok(ChatMessage::DirListing {
+        request_id: request_id.to_string(),
+        path: display_path(&root, &dir),
+        entries,
+        truncated,
+    })
+}
+
+async fn read_file(config: &ConfigHandle, request_id: &str, app_root: &str, path: &str) -> Result<ChatMessage> {
+    let (root, file_path) = resolve(config, app_root, path).await?;
+
+    let meta = tokio::fs::metadata(&file_path).await?;
+    if !meta.is_file() {
+        bail!("not a regular file: {}", path);
+    }
+
+    let mut buf = Vec::new();
+    tokio::fs::File::open(&file_path)
+        .await?
+        .take(MAX_FILE_BYTES)
+        .read_to_end(&mut buf)
+        .await?;
+
+    let sniff = &buf[..buf.len().min(BINARY_SNIFF_BYTES)];
+    let binary = sniff.contains(&0);
+    let content = (!binary).then(|| String::from_utf8_lossy(&buf).to_string());
+
+    Ok(ChatMessage::FileContent {
+        request_id: request_id.to_string(),
+        path: display_path(&root, &file_path),
+        content,
+        size: meta.len(),
+        truncated: meta.len() > MAX_FILE_BYTES,
+        binary,
+    })
+}
+
+pub async fn handle_list_dir(
+    config: &ConfigHandle,
+    out_tx: &mpsc::Sender<ChatMessage>,
+    request_id: &str,
+    app_root: &str,
+    path: &str,
+) {
+    let message = list_dir(config, request_id, app_root, path)
+        .await
+        .unwrap_or_else(|e| ChatMessage::request_failed(request_id, e.to_string()));
+    let _ = out_tx.send(message).await;
+}
+
+pub async fn handle_read_file(
+    config: &ConfigHandle,
+    out_tx: &mpsc::Sender<ChatMessage>,
+    request_id: &str,
+    app_root: &str,
+    path: &str,
+) {
+    let message = read_file(config, request_id, app_root, path)
+        .await
+        .unwrap_or_else(|e| ChatMessage::request_failed(request_id, e.to_string()));
+    let _ = out_tx.send(message).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn resolve_stays_inside_the_app_root() {
+        let dir = std::env::temp_dir().join(format!("teeclaude-files-{}", uuid::Uuid::new_v4()));
+        for sub in ["app/src", "outside"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        let dir = std::fs::canonicalize(&dir).unwrap();
+        std::fs::write(dir.join("app/src/main.rs"), "").unwrap();
+        std::fs::write(dir.join("outside/secret"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), dir.join("app/escape")).unwrap();
+
+        // Registered as written, without canonicalizing
+        let app_root = dir.join("app/src/..").to_string_lossy().to_string();
+        let mut config = Config::load_or_create(&dir.to_string_lossy()).unwrap();
+        config.ensure_app(&app_root);
+        config.save().unwrap();
+        let config = ConfigHandle::spawn(config);
+
+        let path = |p: &str| dir.join(p).to_string_lossy().to_string();
+
+        let relative = resolve(&config, &app_root, "src/main.rs").await.ok().map(|(_, p)| p);
+        let root = resolve(&config, &app_root, ".").await.ok();
+        let absolute_inside = resolve(&config, &app_root, &path("app/src")).await.ok().map(|(_, p)| p);
+        let dotdot = resolve(&config, &app_root, "../outside/secret").await;
+        let absolute_outside = resolve(&config, &app_root, &path("outside/secret")).await;
+        let symlink = resolve(&config, &app_root, "escape/secret").await;
+        let unknown_root = resolve(&config, &path("outside"), "secret").await;
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(relative, Some(dir.join("app/src/main.rs")));
+        assert_eq!(root, Some((dir.join("app"), dir.join("app"))));
+        assert_eq!(absolute_inside, Some(dir.join("app/src")));
+        assert!(dotdot.is_err());
+        assert!(absolute_outside.is_err());
+        assert!(symlink.is_err());
+        assert!(unknown_root.is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use teeclaude_common::FileChange;
+
+/// Git's well-known empty tree, used as the base in repositories without commits.
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+const MAX_DIFF_BYTES: usize = 1024 * 1024;
+/// Sandboxed agents can write to the repositories the listener runs git in, so
+/// nothing in them may make git run a program.
+const SAFE_CONFIG: &[&str] = &["-c", "core.fsmonitor=false", "-c", "core.hooksPath=/dev/null"];
+
+/// Working tree state of a repository at the start of a chat turn.
+pub struct Snapshot {
+    toplevel: PathBuf,
+    /// Tree of every tracked and untracked (not ignored) file, so changes made
+    /// before the turn are not counted as the turn's
+    tree: String,
+}
+
+impl Snapshot {
+    /// Tree the turn started from, for requesting the turn's diff later.
+    pub fn base(&self) -> &str {
+        &self.tree
+    }
+}
+
+pub async fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    run_git(dir, None, args).await
+}
+
+async fn run_git(dir: &Path, index: Option<&Path>, args: &[&str]) -> Result<String> {
+    let mut command = tokio::process::Command::new("git");
+    command.arg("-C").arg(dir).args(SAFE_CONFIG).args(args);
+    if let Some(index) = index {
+        command.env("GIT_INDEX_FILE", index);
+    }
+    let output = command.output().await?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Write the working tree, untracked files included, as a tree object. A copy
+/// of the index is used so the user's staging area is left alone.
+async fn working_tree(toplevel: &Path) -> Result<String> {
+    let index = git(toplevel, &["rev-parse", "--git-path", "index"]).await?;
+    let temp = std::env::temp_dir().join(format!("teeclaude-index-{}", uuid::Uuid::new_v4()));
+    // Starting from the real index lets git skip rehashing unchanged files
+    if let Err(e) = tokio::fs::copy(toplevel.join(index.trim()), &temp).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            bail!("failed to copy the git index: {}", e);
+        }
+    }
+    let tree = async {
+        run_git(toplevel, Some(&temp), &["add", "-A", "--", "."]).await?;
+        run_git(toplevel, Some(&temp), &["write-tree"]).await
+    }
+    .await;
+    let _ = tokio::fs::remove_file(&temp).await;
+    Ok(tree?.trim().to_string())
+}
+
+/// Snapshot the repository containing `app_root`; None when it is not git-backed.
+pub async fn snapshot(app_root: &str) -> Option<Snapshot> {
+    let root = Path::new(app_root);
+    let toplevel = PathBuf::from(git(root, &["rev-parse", "--show-toplevel"]).await.ok()?.trim());
+    match working_tree(&toplevel).await {
+        Ok(tree) => Some(Snapshot { toplevel, tree }),
+        Err(e) => {
+            eprintln!("[git] failed to snapshot {}: {}", toplevel.display(), e);
+            None
+        }
+    }
+}
+
+/// Parse `git diff --name-status -z` into (path, status) pairs, keyed by the
+/// new path for renames and copies.
+fn parse_name_status(output: &str) -> Vec<(String, &'static str)> {
+    let mut entries = Vec::new();
+    let mut fields = output.split('\0').filter(|f| !f.is_empty());
+    while let Some(code) = fields.next() {
+        let status = match code.chars().next() {
+            Some('A') | Some('C') => "added",
+            Some('D') => "deleted",
+            Some('R') => "renamed",
+            _ => "modified",
+        };
+        // Renames and copies list the old path before the new one
+        if code.starts_with(['R', 'C']) {
+            fields.next();
+        }
+        let Some(path) = fields.next() else {
+            break;
+        };
+        entries.push((path.to_string(), status));
+    }
+    entries
+}
+
+/// Parse `git diff --numstat -z`; binary files have no line counts.
+fn parse_numstat(output: &str) -> HashMap<String, (Option<u64>, Option<u64>)> {
+    let mut counts = HashMap::new();
+    let mut records = output.split('\0');
+    while let Some(record) = records.next() {
+        let mut parts = record.splitn(3, '\t');
+        let (Some(added), Some(deleted), Some(path)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        // Renames have an empty path followed by the old and new paths
+        let path = if path.is_empty() {
+            records.next();
+            match records.next() {
+                Some(new_path) if !new_path.is_empty() => new_path,
+                _ => break,
+            }
+        } else {
+            path
+        };
+        counts.insert(path.to_string(), (added.parse().ok(), deleted.parse().ok()));
+    }
+    counts
+}
+
+/// Files that changed since `before`, with line counts relative to it.
+pub async fn changes_since(before: &Snapshot) -> Result<Vec<FileChange>> {
+    let after = working_tree(&before.toplevel).await?;
+    let range = [before.tree.as_str(), after.as_str()];
+
+    let mut args = vec!["diff", "--name-status", "-z", "-M"];
+    args.extend(range);
+    let statuses = parse_name_status(&git(&before.toplevel, &args).await?);
+    let mut args = vec!["diff", "--numstat", "-z", "-M"];
+    args.extend(range);
+    let counts = parse_numstat(&git(&before.toplevel, &args).await?);
+
+    let mut files: Vec<FileChange> = statuses
+        .into_iter()
+        .map(|(path, status)| {
+            let (additions, deletions) = counts.get(&path).copied().unwrap_or((Some(0), Some(0)));
+            FileChange {
+                path,
+                status: status.to_string(),
+                additions,
+                deletions,
+            }
+        })
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Unified diff of the working tree against `base`, including untracked files.
+pub async fn diff(app_root: &str, base: Option<&str>, paths: &[String]) -> Result<(String, bool)> {
+    // Paths are relative to the repository top level, as reported in TurnChanges
+    let toplevel = git(Path::new(app_root), &["rev-parse", "--show-toplevel"]).await?;
+    let root = Path::new(toplevel.trim());
+    let base = match base {
+        Some(b) => b.to_string(),
+        None => git(root, &["rev-parse", "--verify", "-q", "HEAD"])
+            .await
+            .map(|h| h.trim().to_string())
+            .unwrap_or_else(|_| EMPTY_TREE.to_string()),
+    };
+    if base.starts_with('-') {
+        bail!("invalid base: {}", base);
+    }
+
+    // Comparing two trees picks up untracked files on both sides
+    let current = working_tree(root).await?;
+    let mut args = vec!["diff", base.as_str(), current.as_str(), "--"];
+    args.extend(paths.iter().map(String::as_str));
+    let mut diff = git(root, &args).await?;
+
+    let truncated = diff.len() > MAX_DIFF_BYTES;
+    if truncated {
+        let mut end = MAX_DIFF_BYTES;
+        while !diff.is_char_boundary(end) {
+            end -= 1;
+        }
+        diff.truncate(end);
+    }
+    Ok((diff, truncated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_status_keys_renames_by_new_path() {
+        let output = "M\0src/lib.rs\0A\0new.txt\0D\0gone.txt\0R087\0old name.rs\0new name.rs\0C100\0a.rs\0b.rs\0";
+        assert_eq!(
+            parse_name_status(output),
+            vec![
+                ("src/lib.rs".to_string(), "modified"),
+                ("new.txt".to_string(), "added"),
+                ("gone.txt".to_string(), "deleted"),
+                ("new name.rs".to_string(), "renamed"),
+                ("b.rs".to_string(), "added"),
+            ]
+        );
+    }
+
+    #[test]
+    fn numstat_handles_renames_and_binaries() {
+        let output = concat!(
+            "3\t1\tsrc/lib.rs\0",
+            "-\t-\tlogo.png\0",
+            "2\t0\t\0old.rs\0new.rs\0",
+        );
+        let counts = parse_numstat(output);
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts["src/lib.rs"], (Some(3), Some(1)));
+        assert_eq!(counts["logo.png"], (None, None));
+        assert_eq!(counts["new.rs"], (Some(2), Some(0)));
+        assert!(!counts.contains_key("old.rs"));
+    }
+
+    #[test]
+    fn numstat_ignores_truncated_renames() {
+        let counts = parse_numstat("1\t1\t\0old.rs\0");
+        assert!(counts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn changes_exclude_edits_made_before_the_turn() {
+        let dir = std::env::temp_dir().join(format!("teeclaude-git-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let root = dir.to_string_lossy().to_string();
+        for args in [
+            &["init", "-q"][..],
+            &["config", "user.email", "test@example.com"],
+            &["config", "user.name", "test"],
+        ] {
+            git(&dir, args).await.unwrap();
+        }
+        std::fs::write(dir.join("kept.txt"), "one\n").unwrap();
+        git(&dir, &["add", "."]).await.unwrap();
+        git(&dir, &["commit", "-q", "-m", "init"]).await.unwrap();
+
+        // Uncommitted work from before the turn
+        std::fs::write(dir.join("kept.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(dir.join("draft.txt"), "draft\n").unwrap();
+        let before = snapshot(&root).await.unwrap();
+
+        std::fs::write(dir.join("draft.txt"), "draft\nmore\n").unwrap();
+        std::fs::write(dir.join("turn.txt"), "a\nb\n").unwrap();
+        let files = changes_since(&before).await.unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let summary: Vec<_> = files
+            .iter()
+            .map(|f| (f.path.as_str(), f.status.as_str(), f.additions, f.deletions))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("draft.txt", "modified", Some(1), Some(0)),
+                ("turn.txt", "added", Some(2), Some(0)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn repository_config_cannot_run_programs() {
+        let dir = std::env::temp_dir().join(format!("teeclaude-git-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let marker = dir.join("ran");
+        git(&dir, &["init", "-q"]).await.unwrap();
+        let touch = format!("touch {}", marker.display());
+        git(&dir, &["config", "core.fsmonitor", &touch]).await.unwrap();
+        let hook = dir.join(".git/hooks/pre-commit");
+        std::fs::write(&hook, format!("#!/bin/sh\n{}\n", touch)).unwrap();
+        std::fs::set_permissions(&hook, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+
+        std::fs::write(dir.join("file.txt"), "x\n").unwrap();
+        snapshot(&dir.to_string_lossy()).await.unwrap();
+        git(&dir, &["add", "-A"]).await.unwrap();
+        let commit = ["-c", "user.email=test@example.com", "-c", "user.name=test", "commit", "-q", "-m", "x"];
+        git(&dir, &commit).await.unwrap();
+        let ran = marker.exists();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(!ran);
+    }
+}
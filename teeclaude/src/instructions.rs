@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::daemon;
+
+/// Optional user-wide override, used when the config sets no `instructions_path`.
+const USER_INSTRUCTIONS_FILE: &str = "instructions.md";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstructionsLang {
+    #[default]
+    En,
+    Zh,
+}
+
+const TEMPLATE_EN: &str = r#"# TeeClaude
+
+## ui_call protocol
+
+To make the UI do something, write a `<ui_call>` tag in your output:
+
+```
+<ui_call>["refresh_apps"]</ui_call>
+```
+
+The first array element is the command name; the rest are its arguments. Supported commands:
+- `["refresh_apps"]` — reload `.teeclaude.json` and refresh the app list in the UI
+- `["open_file", "<path>", <line>]` — open a file in the UI; `line` is optional
+- `["show_diff", "<path>", ...]` — show changes in the UI; without paths, shows all changes
+- `["notify", "<message>", "<level>"]` — show a notification; `level` is `info`, `success`, `warning` or `error` and is optional
+
+Note: `<ui_call>` blocks are removed from the output and never shown in the UI; they are an internal protocol only.
+
+## /init-project skill
+
+When the user wants to set up a new project:
+
+1. Ask the user for the GitHub repository URL
+2. Run `git clone <repo_url>` to clone it into the current root directory
+3. Edit `.teeclaude.json` and add an entry for the new app to the `apps` array:
+   ```json
+   { "root": "<absolute_path_to_cloned_repo>", "sessions": [] }
+   ```
+4. Output `<ui_call>["refresh_apps"]</ui_call>` so the UI refreshes
+"#;
+
+const TEMPLATE_ZH: &str = r#"# TeeClaude
+
+## ui_call 协议
+
+当你需要让 UI 执行操作时，在输出中使用 `<ui_call>` 标签：
+
+```
+<ui_call>["refresh_apps"]</ui_call>
+```
+
+数组第一个元素是命令名，其余是参数。支持的命令：
+- `["refresh_apps"]` — 重新加载 `.teeclaude.json` 并刷新 UI 的 app 列表
+- `["open_file", "<path>", <line>]` — 在 UI 中打开文件，`line` 可省略
+- `["show_diff", "<path>", ...]` — 在 UI 中显示改动，不带路径时显示全部改动
+- `["notify", "<message>", "<level>"]` — 显示通知，`level` 为 `info`、`success`、`warning` 或 `error`，可省略
+
+注意：`<ui_call>` 块会从输出中移除，不会显示在 UI 中，仅作为内部通信协议。
+
+## /init-project skill
+
+当用户想要初始化一个新项目时：
+
+1. 引导用户提供 GitHub 仓库地址
+2. 使用 `git clone <repo_url>` 将项目 clone 到当前 root 目录下
+3. 编辑 `.teeclaude.json`，在 `apps` 数组中添加新 app 条目：
+   ```json
+   { "root": "<absolute_path_to_cloned_repo>", "sessions": [] }
+   ```
+4. 输出 `<ui_call>["refresh_apps"]</ui_call>` 通知 UI 刷新
+"#;
+
+fn user_instructions_path() -> PathBuf {
+    daemon::gateway_dir().join(USER_INSTRUCTIONS_FILE)
+}
+
+/// TeeClaude's instructions for the agent, appended to its system prompt.
+///
+/// Taken from `instructions_path` when configured, else from
+/// `~/.teeclaude/instructions.md` when it exists, else the built-in template
+/// in `instructions_lang`. Nothing is ever written into the app's tree.
+pub fn load(config: &Config) -> Result<String> {
+    let (path, required) = match &config.instructions_path {
+        Some(p) => (p.clone(), true),
+        None => (user_instructions_path(), false),
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(text),
+        Err(e) if required || e.kind() != std::io::ErrorKind::NotFound => Err(anyhow!(
+            "failed to read instructions {}: {}",
+            path.display(),
+            e
+        )),
+        Err(_) => Ok(match config.instructions_lang {
+            InstructionsLang::En => TEMPLATE_EN,
+            InstructionsLang::Zh => TEMPLATE_ZH,
+        }
+        .to_string()),
+    }
+}
@@ -1,14 +1,29 @@
 use anyhow::Result;
 use futures::{SinkExt, StreamExt};
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
 
 use teeclaude_common::ChatMessage;
 
-use crate::chat_handler;
-use crate::config::Config;
+use crate::apps;
+use crate::attachments::{self, AttachmentStore};
+use crate::chat_handler::{self, ChatTurn, RunningTurns};
+use crate::config::{Config, ConfigHandle};
+use crate::files;
+use crate::git;
+use crate::permission::{PermissionBroker, PermissionDecision};
+use crate::schedule;
+use crate::sessions;
+use crate::terminal::{self, Terminals};
+use crate::transcript;
+use crate::url;
+use crate::worktree;
 
-pub async fn run(server_url: &str, root: Option<&str>) -> Result<()> {
+/// How long running turns get to stop when the listener shuts down
+const STOP_TURNS_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub async fn run(server: &str, token: Option<&str>, root: Option<&str>) -> Result<()> {
     let app_root = match root {
         Some(r) => std::path::Path::new(r)
             .canonicalize()?
@@ -17,16 +32,27 @@ pub async fn run(server_url: &str, root: Option<&str>) -> Result<()> {
         None => std::env::current_dir()?.to_string_lossy().to_string(),
     };
 
-    chat_handler::ensure_claude_md(&app_root);
-
     let mut config = Config::load_or_create(&app_root)?;
     config.ensure_app(&app_root);
     config.save()?;
 
     let apps = config.to_app_infos();
+    let app_roots: Vec<String> = config.apps.iter().map(|a| a.root.clone()).collect();
+    tokio::spawn(async move {
+        for root in app_roots {
+            match attachments::remove_expired(&root).await {
+                Ok(0) => {}
+                Ok(n) => eprintln!("[attachments] removed {} expired upload(s) in {}", n, root),
+                Err(e) => eprintln!("[attachments] cleanup failed in {}: {}", root, e),
+            }
+        }
+    });
+    let permission_timeout = std::time::Duration::from_secs(config.permission_timeout_secs);
+    let config = ConfigHandle::spawn(config);
 
+    let server_url = url::build_ws_url(server, token, "/ws/listener");
     eprintln!("Connecting to {}...", server_url);
-    let (ws_stream, _) = connect_async(server_url).await?;
+    let (ws_stream, _) = connect_async(&server_url).await?;
     eprintln!("Connected. Waiting for chat messages...");
 
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
@@ -37,6 +63,19 @@ pub async fn run(server_url: &str, root: Option<&str>) -> Result<()> {
 
     let (out_tx, mut out_rx) = mpsc::channel::<ChatMessage>(100);
 
+    let permissions = PermissionBroker::start(out_tx.clone(), permission_timeout)?;
+    let cleanup_permissions = permissions.clone();
+    let attachment_store = AttachmentStore::default();
+    let running_turns = RunningTurns::default();
+
+    tokio::spawn(schedule::run(
+        config.clone(),
+        out_tx.clone(),
+        permissions.clone(),
+        attachment_store.clone(),
+        running_turns.clone(),
+    ));
+
     let send_task = tokio::spawn(async move {
         while let Some(msg) = out_rx.recv().await {
             let json = match serde_json::to_string(&msg) {
@@ -49,6 +88,11 @@ pub async fn run(server_url: &str, root: Option<&str>) -> Result<()> {
         }
     });
 
+    let chunk_tx = spawn_chunk_writer(config.clone(), attachment_store.clone(), out_tx.clone());
+
+    let shutdown_turns = running_turns.clone();
+    let terminals = Terminals::new(server);
+    let shutdown_terminals = terminals.clone();
     let recv_task = tokio::spawn(async move {
         while let Some(Ok(msg)) = ws_receiver.next().await {
             let text = match msg {
@@ -65,34 +109,290 @@ pub async fn run(server_url: &str, root: Option<&str>) -> Result<()> {
 
             match message {
                 ChatMessage::ChatInput {
+                    request_id,
                     chat_session_id,
                     app_root,
                     content,
+                    options,
+                    attachments,
                 } => {
                     eprintln!(
                         "[listener] received ChatInput: session={:?}, app_root={}, content_len={}",
                         chat_session_id, app_root, content.len()
                     );
                     let out_tx = out_tx.clone();
-                    let mut config = config.clone();
+                    let config = config.clone();
+                    let permissions = permissions.clone();
+                    let attachment_store = attachment_store.clone();
+                    let running_turns = running_turns.clone();
                     tokio::spawn(async move {
+                        // A refused message has no session yet, so it goes back to the sender only
+                        let resolved = turn_app_root(&config, &app_root, chat_session_id.as_deref()).await;
+                        let app_root = match resolved {
+                            Ok(root) => root,
+                            Err(e) => {
+                                eprintln!(
+                                    "[listener] rejected ChatInput: session={:?}, app_root={}, {}",
+                                    chat_session_id, app_root, e
+                                );
+                                let _ = out_tx
+                                    .send(ChatMessage::request_failed(request_id, e.to_string()))
+                                    .await;
+                                return;
+                            }
+                        };
+                        let new_session = chat_session_id.is_none();
+                        let chat_session_id =
+                            chat_session_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+                        let _ = out_tx
+                            .send(ChatMessage::ChatAccepted {
+                                request_id,
+                                chat_session_id: chat_session_id.clone(),
+                            })
+                            .await;
+                        let turn = ChatTurn {
+                            chat_session_id,
+                            new_session,
+                            app_root,
+                            content,
+                            options,
+                            attachments,
+                            permission_mode: None,
+                        };
                         chat_handler::handle_chat_input(
-                            &mut config,
+                            &config,
                             &out_tx,
-                            chat_session_id,
-                            &app_root,
-                            &content,
+                            &permissions,
+                            &attachment_store,
+                            &running_turns,
+                            turn,
                         )
                         .await;
                     });
                 }
+                ChatMessage::AttachmentChunk {
+                    attachment_id,
+                    chat_session_id,
+                    app_root,
+                    file_name,
+                    offset,
+                    data,
+                    last,
+                } => {
+                    let chunk = attachments::Chunk {
+                        attachment_id,
+                        chat_session_id,
+                        app_root,
+                        file_name,
+                        offset,
+                        data,
+                        last,
+                    };
+                    let _ = chunk_tx.send(chunk);
+                }
                 ChatMessage::ResyncApps => {
-                    if let Ok(fresh) = Config::load_or_create(&app_root) {
-                        let _ = out_tx
-                            .send(ChatMessage::ListenerReady {
-                                apps: fresh.to_app_infos(),
-                            })
+                    if let Err(e) = config.reload().await {
+                        eprintln!("[listener] failed to reload config: {}", e);
+                    }
+                    send_listener_ready(&config, &out_tx).await;
+                }
+                ChatMessage::LoadTranscript {
+                    chat_session_id,
+                    before,
+                    limit,
+                } => {
+                    let out_tx = out_tx.clone();
+                    let config = config.clone();
+                    tokio::spawn(async move {
+                        transcript::handle_load_transcript(
+                            &config,
+                            &out_tx,
+                            &chat_session_id,
+                            before,
+                            limit,
+                        )
+                        .await;
+                    });
+                }
+                ChatMessage::RenameSession {
+                    chat_session_id,
+                    name,
+                } => {
+                    let result = sessions::rename(&config, &chat_session_id, &name).await;
+                    sessions::report(&config, &out_tx, &chat_session_id, result).await;
+                }
+                ChatMessage::ArchiveSession {
+                    chat_session_id,
+                    archived,
+                } => {
+                    let result = sessions::archive(&config, &chat_session_id, archived).await;
+                    sessions::report(&config, &out_tx, &chat_session_id, result).await;
+                }
+                ChatMessage::DeleteSession {
+                    chat_session_id,
+                    remove_transcript,
+                } => {
+                    let (out_tx, config, running) =
+                        (out_tx.clone(), config.clone(), running_turns.clone());
+                    tokio::spawn(async move {
+                        let result =
+                            sessions::delete(&config, &running, &chat_session_id, remove_transcript)
+                                .await;
+                        sessions::report(&config, &out_tx, &chat_session_id, result).await;
+                    });
+                }
+                ChatMessage::ForkSession { chat_session_id } => {
+                    let (out_tx, config) = (out_tx.clone(), config.clone());
+                    tokio::spawn(async move {
+                        let result = sessions::fork(&config, &out_tx, &chat_session_id).await;
+                        sessions::report(&config, &out_tx, &chat_session_id, result).await;
+                    });
+                }
+                ChatMessage::ListDir {
+                    request_id,
+                    app_root,
+                    path,
+                } => {
+                    let out_tx = out_tx.clone();
+                    let config = config.clone();
+                    tokio::spawn(async move {
+                        files::handle_list_dir(&config, &out_tx, &request_id, &app_root, &path)
+                            .await;
+                    });
+                }
+                ChatMessage::ReadFile {
+                    request_id,
+                    app_root,
+                    path,
+                } => {
+                    let out_tx = out_tx.clone();
+                    let config = config.clone();
+                    tokio::spawn(async move {
+                        files::handle_read_file(&config, &out_tx, &request_id, &app_root, &path)
                             .await;
+                    });
+                }
+                ChatMessage::GetDiff {
+                    request_id,
+                    app_root,
+                    chat_session_id,
+                    base,
+                    paths,
+                } => {
+                    let out_tx = out_tx.clone();
+                    let config = config.clone();
+                    tokio::spawn(async move {
+                        let diff = async {
+                            let dir = diff_dir(&config, app_root, chat_session_id).await?;
+                            git::diff(&dir, base.as_deref(), &paths).await
+                        };
+                        let message = match diff.await {
+                            Ok((diff, truncated)) => ChatMessage::DiffContent {
+                                request_id,
+                                diff,
+                                truncated,
+                            },
+                            Err(e) => ChatMessage::request_failed(request_id, e.to_string()),
+                        };
+                        let _ = out_tx.send(message).await;
+                    });
+                }
+                ChatMessage::AddApp {
+                    request_id,
+                    path,
+                    git_url,
+                    name,
+                } => {
+                    let out_tx = out_tx.clone();
+                    let config = config.clone();
+                    tokio::spawn(async move {
+                        apps::handle_add_app(
+                            &config,
+                            &out_tx,
+                            &request_id,
+                            path.as_deref(),
+                            git_url.as_deref(),
+                            name.as_deref(),
+                        )
+                        .await;
+                    });
+                }
+                ChatMessage::RemoveApp {
+                    request_id,
+                    app_root,
+                } => {
+                    apps::handle_remove_app(&config, &out_tx, &request_id, &app_root).await;
+                }
+                ChatMessage::MergeSession {
+                    request_id,
+                    chat_session_id,
+                } => {
+                    let (out_tx, config, running) =
+                        (out_tx.clone(), config.clone(), running_turns.clone());
+                    tokio::spawn(async move {
+                        worktree::handle_resolve(
+                            &config,
+                            &out_tx,
+                            &running,
+                            &request_id,
+                            &chat_session_id,
+                            true,
+                        )
+                        .await;
+                    });
+                }
+                ChatMessage::DiscardSession {
+                    request_id,
+                    chat_session_id,
+                } => {
+                    let (out_tx, config, running) =
+                        (out_tx.clone(), config.clone(), running_turns.clone());
+                    tokio::spawn(async move {
+                        worktree::handle_resolve(
+                            &config,
+                            &out_tx,
+                            &running,
+                            &request_id,
+                            &chat_session_id,
+                            false,
+                        )
+                        .await;
+                    });
+                }
+                ChatMessage::OpenTerminal {
+                    request_id,
+                    app_root,
+                    command,
+                    terminal_token,
+                } => {
+                    let (out_tx, config, terminals) = (out_tx.clone(), config.clone(), terminals.clone());
+                    tokio::spawn(async move {
+                        terminal::handle_open_terminal(
+                            &config,
+                            &terminals,
+                            &out_tx,
+                            &request_id,
+                            &app_root,
+                            command.as_deref(),
+                            &terminal_token,
+                        )
+                        .await;
+                    });
+                }
+                ChatMessage::PermissionResponse {
+                    request_id,
+                    allow,
+                    message,
+                } => {
+                    permissions
+                        .resolve(&request_id, PermissionDecision { allow, message })
+                        .await;
+                }
+                ChatMessage::CancelTurn { chat_session_id } => {
+                    if chat_handler::cancel_turn(&running_turns, &chat_session_id) {
+                        eprintln!("[listener] cancelling turn: session={}", chat_session_id);
+                    } else {
+                        eprintln!("[listener] no running turn to cancel: session={}", chat_session_id);
                     }
                 }
                 _ => {}
@@ -117,5 +417,103 @@ pub async fn run(server_url: &str, root: Option<&str>) -> Result<()> {
         }
     }
 
+    // Agents run in their own process groups, so they would outlive the listener
+    chat_handler::stop_all(&shutdown_turns, STOP_TURNS_TIMEOUT).await;
+    shutdown_terminals.kill_all();
+    cleanup_permissions.cleanup();
     Ok(())
 }
+
+/// Send the current apps and their sessions so the server and every browser
+/// see the same session list.
+/// Resolve the app a message is for. An existing session runs under the
+/// policy of the app that owns it, so naming a different app is refused.
+async fn turn_app_root(config: &ConfigHandle, app_root: &str, chat_session_id: Option<&str>) -> Result<String> {
+    let app_root = apps::resolve_app_root(config, app_root).await?;
+    if let Some(id) = chat_session_id {
+        let id = id.to_string();
+        if let Some(owner) = config.read(move |c| c.session_app_root(&id)).await? {
+            if owner != app_root {
+                anyhow::bail!("chat session belongs to {}", owner);
+            }
+        }
+    }
+    Ok(app_root)
+}
+
+/// Directory to diff: the app root, or the working directory of one of its
+/// sessions, where that session's turn bases were taken.
+async fn diff_dir(config: &ConfigHandle, app_root: String, chat_session_id: Option<String>) -> Result<String> {
+    config
+        .read(move |c| {
+            if !c.has_app(&app_root) {
+                anyhow::bail!("unknown app root: {}", app_root);
+            }
+            let Some(id) = chat_session_id else {
+                return Ok(app_root);
+            };
+            match c.session_app_root(&id) {
+                Some(owner) if owner == app_root => Ok(c.session_cwd(&id).unwrap_or(app_root)),
+                _ => anyhow::bail!("unknown chat session: {}", id),
+            }
+        })
+        .await?
+}
+
+pub async fn send_listener_ready(config: &ConfigHandle, out_tx: &mpsc::Sender<ChatMessage>) {
+    if let Ok(apps) = config.read(|c| c.to_app_infos()).await {
+        let _ = out_tx.send(ChatMessage::ListenerReady { apps }).await;
+    }
+}
+
+/// Write upload chunks on a task of their own, so a large upload does not
+/// hold up cancellation and permission answers. Chunks are written in the
+/// order they arrived.
+fn spawn_chunk_writer(
+    config: ConfigHandle,
+    store: AttachmentStore,
+    out_tx: mpsc::Sender<ChatMessage>,
+) -> mpsc::UnboundedSender<attachments::Chunk> {
+    let (chunk_tx, mut chunk_rx) = mpsc::unbounded_channel::<attachments::Chunk>();
+    tokio::spawn(async move {
+        while let Some(chunk) = chunk_rx.recv().await {
+            let attachment_id = chunk.attachment_id.clone();
+            let reply = match store_chunk(&config, &store, chunk).await {
+                Ok(Some(attachment)) => Some(ChatMessage::AttachmentStored {
+                    attachment_id,
+                    path: attachment.path.to_string_lossy().to_string(),
+                    size: attachment.size,
+                    timestamp: chrono::Utc::now(),
+                }),
+                Ok(None) => None,
+                Err(e) => {
+                    eprintln!("[listener] attachment {}: {}", attachment_id, e);
+                    Some(ChatMessage::AttachmentFailed {
+                        attachment_id,
+                        error: e.to_string(),
+                        timestamp: chrono::Utc::now(),
+                    })
+                }
+            };
+            if let Some(reply) = reply {
+                let _ = out_tx.send(reply).await;
+            }
+        }
+    });
+    chunk_tx
+}
+
+async fn store_chunk(
+    config: &ConfigHandle,
+    store: &AttachmentStore,
+    chunk: attachments::Chunk,
+) -> Result<Option<attachments::Attachment>> {
+    let root = chunk.app_root.clone();
+    let (registered, max_bytes) = config
+        .read(move |c| (c.has_app(&root), c.max_attachment_bytes))
+        .await?;
+    if !registered {
+        anyhow::bail!("unknown app root: {}", chunk.app_root);
+    }
+    store.write_chunk(chunk, max_bytes).await
+}
@@ -1,13 +1,31 @@
+mod agent;
+mod apps;
+mod attachments;
+mod chat_client;
 mod chat_handler;
 mod config;
 mod daemon;
+mod files;
+mod git;
+mod instructions;
 mod listener;
+mod mcp;
+mod permission;
 mod pty;
+mod sandbox;
+mod schedule;
+mod sessions;
+mod terminal;
+mod transcript;
+mod ui_call;
 mod url;
+mod worktree;
 mod ws_client;
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
+use portable_pty::PtySize;
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "teeclaude", about = "Claude Code session wrapper with remote viewing")]
@@ -24,6 +42,22 @@ struct Cli {
     #[arg(short, long)]
     name: Option<String>,
 
+    /// Wrap without a local terminal; the session is used from the browser
+    #[arg(long)]
+    headless: bool,
+
+    /// Terminal size in headless mode, as COLSxROWS [default: 120x30]
+    #[arg(long, value_parser = pty::parse_size)]
+    size: Option<PtySize>,
+
+    /// Append the session's output to this file in headless mode
+    #[arg(long)]
+    log: Option<PathBuf>,
+
+    /// Keep a headless session attached to the launching shell
+    #[arg(long)]
+    foreground: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -44,25 +78,136 @@ enum Commands {
     Stop,
     /// Show daemon status
     Status,
+    /// Talk to chat sessions through the server
+    Chat {
+        #[command(subcommand)]
+        command: ChatCommand,
+    },
+    /// MCP permission prompt server (spawned by claude)
+    #[command(hide = true)]
+    PermissionMcp {
+        /// Listener permission socket
+        #[arg(long)]
+        socket: String,
+
+        /// Chat session the prompts belong to
+        #[arg(long)]
+        session: String,
+    },
     /// Wrap a command (terminal mode)
     #[command(external_subcommand)]
     Wrap(Vec<String>),
 }
 
+#[derive(Subcommand)]
+enum ChatCommand {
+    /// Send a message and print the reply
+    Send {
+        /// App root the session belongs to
+        #[arg(long)]
+        app: String,
+
+        /// Session to continue (starts a new session if omitted)
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Print the session id once the message is accepted instead of the reply
+        #[arg(long)]
+        no_wait: bool,
+
+        /// Print events as NDJSON
+        #[arg(long)]
+        json: bool,
+
+        /// Message text
+        #[arg(required = true, num_args = 1..)]
+        message: Vec<String>,
+    },
+    /// Print chat events as they arrive
+    Follow {
+        /// Only show this session
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Print events as NDJSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+fn chat_format(json: bool) -> chat_client::Format {
+    if json {
+        chat_client::Format::Json
+    } else {
+        chat_client::Format::Text
+    }
+}
+
+/// Reject the headless flags anywhere but on a headless wrapped command.
+fn check_headless_flags(cli: &Cli) {
+    let flags = [
+        ("--headless", cli.headless),
+        ("--size", cli.size.is_some()),
+        ("--log", cli.log.is_some()),
+        ("--foreground", cli.foreground),
+    ];
+    let Some((flag, _)) = flags.iter().find(|(_, set)| *set) else {
+        return;
+    };
+    let message = if !matches!(cli.command, Commands::Wrap(_)) {
+        format!("{} only applies when wrapping a command", flag)
+    } else if !cli.headless {
+        format!("{} requires --headless", flag)
+    } else {
+        return;
+    };
+    Cli::command().error(ErrorKind::ArgumentConflict, message).exit();
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    check_headless_flags(&cli);
 
     match cli.command {
         Commands::Start { daemon: true, root } => {
             daemon::start(&cli.server, cli.token.as_deref(), root.as_deref())
         }
         Commands::Start { daemon: false, root } => {
-            let ws_url = url::build_ws_url(&cli.server, cli.token.as_deref(), "/ws/listener");
-            listener::run(&ws_url, root.as_deref()).await
+            listener::run(&cli.server, cli.token.as_deref(), root.as_deref()).await
         }
         Commands::Stop => daemon::stop(cli.token.as_deref()),
         Commands::Status => daemon::status(cli.token.as_deref()),
+        Commands::Chat {
+            command:
+                ChatCommand::Send {
+                    app,
+                    session,
+                    no_wait,
+                    json,
+                    message,
+                },
+        } => {
+            chat_client::send(
+                &cli.server,
+                cli.token.as_deref(),
+                &app,
+                session.as_deref(),
+                &message.join(" "),
+                !no_wait,
+                chat_format(json),
+            )
+            .await
+        }
+        Commands::Chat {
+            command: ChatCommand::Follow { session, json },
+        } => {
+            chat_client::follow(&cli.server, cli.token.as_deref(), session.as_deref(), chat_format(json))
+                .await
+        }
+        Commands::PermissionMcp { socket, session } => {
+            mcp::run_permission_server(&socket, &session).await
+        }
         Commands::Wrap(args) => {
             if args.is_empty() {
                 eprintln!("Usage: teeclaude [--server URL] [--token TOKEN] <command> [args...]");
@@ -80,7 +225,15 @@ async fn main() -> Result<()> {
                     .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
             });
 
-            pty::run(command, cmd_args, &ws_url, name.as_deref()).await
+            if !cli.headless {
+                return pty::run(command, cmd_args, &ws_url, name.as_deref()).await;
+            }
+            if !cli.foreground {
+                return daemon::detach(cli.log.as_deref());
+            }
+            let size = cli.size.unwrap_or(pty::HEADLESS_SIZE);
+            pty::run_headless(command, cmd_args, &ws_url, name.as_deref(), size, cli.log.as_deref())
+                .await
         }
     }
 }
@@ -0,0 +1,134 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+use crate::permission::{PermissionDecision, PermissionQuery, MCP_SERVER_NAME, MCP_TOOL_NAME};
+
+const DEFAULT_PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Minimal MCP stdio server exposing the permission prompt tool.
+///
+/// Spawned by claude via `--mcp-config`; each tool call is forwarded to the
+/// listener's permission socket and blocks until the browser answers.
+pub async fn run_permission_server(socket: &str, chat_session_id: &str) -> Result<()> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(line) = lines.next_line().await? {
+        let Ok(request) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        // Notifications carry no id and expect no response
+        let Some(id) = request.get("id").cloned() else {
+            continue;
+        };
+        let method = request.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+        let response = match method {
+            "initialize" => rpc_result(id, initialize_result(&params)),
+            "ping" => rpc_result(id, json!({})),
+            "tools/list" => rpc_result(id, json!({ "tools": [approve_tool()] })),
+            "tools/call" => {
+                let result = call_tool(socket, chat_session_id, &params).await;
+                rpc_result(id, result)
+            }
+            _ => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": format!("method not found: {}", method) },
+            }),
+        };
+
+        let mut out = serde_json::to_string(&response)?;
+        out.push('\n');
+        stdout.write_all(out.as_bytes()).await?;
+        stdout.flush().await?;
+    }
+
+    Ok(())
+}
+
+fn rpc_result(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn initialize_result(params: &Value) -> Value {
+    let version = params
+        .get("protocolVersion")
+        .and_then(Value::as_str)
+        .unwrap_or(DEFAULT_PROTOCOL_VERSION);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": {} },
+        "serverInfo": { "name": MCP_SERVER_NAME, "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+fn approve_tool() -> Value {
+    json!({
+        "name": MCP_TOOL_NAME,
+        "description": "Ask the TeeClaude browser user to approve a tool call",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "tool_name": { "type": "string" },
+                "input": { "type": "object" },
+                "tool_use_id": { "type": "string" },
+            },
+            "required": ["tool_name", "input"],
+        },
+    })
+}
+
+async fn call_tool(socket: &str, chat_session_id: &str, params: &Value) -> Value {
+    let name = params.get("name").and_then(Value::as_str).unwrap_or("");
+    let args = params.get("arguments").cloned().unwrap_or(Value::Null);
+    let input = args.get("input").cloned().unwrap_or_else(|| json!({}));
+
+    let decision = if name != MCP_TOOL_NAME {
+        PermissionDecision::deny(format!("unknown tool: {}", name))
+    } else {
+        let query = PermissionQuery {
+            chat_session_id: chat_session_id.to_string(),
+            tool_name: args
+                .get("tool_name")
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .to_string(),
+            input: input.clone(),
+        };
+        ask_listener(socket, &query).await.unwrap_or_else(|e| {
+            eprintln!("[permission-mcp] listener unreachable: {}", e);
+            PermissionDecision::deny(format!("listener unreachable: {}", e))
+        })
+    };
+
+    let behavior = if decision.allow {
+        json!({ "behavior": "allow", "updatedInput": input })
+    } else {
+        json!({
+            "behavior": "deny",
+            "message": decision.message.unwrap_or_else(|| "denied by user".to_string()),
+        })
+    };
+
+    json!({ "content": [{ "type": "text", "text": behavior.to_string() }] })
+}
+
+async fn ask_listener(socket: &str, query: &PermissionQuery) -> Result<PermissionDecision> {
+    let stream = UnixStream::connect(socket).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut json = serde_json::to_string(query)?;
+    json.push('\n');
+    writer.write_all(json.as_bytes()).await?;
+
+    let mut lines = BufReader::new(reader).lines();
+    let line = lines
+        .next_line()
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("listener closed the permission socket"))?;
+    Ok(serde_json::from_str(&line)?)
+}
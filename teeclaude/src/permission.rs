@@ -0,0 +1,178 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+use teeclaude_common::ChatMessage;
+
+use crate::daemon;
+
+pub const MCP_SERVER_NAME: &str = "teeclaude";
+pub const MCP_TOOL_NAME: &str = "approve";
+
+/// Sent by the MCP permission server to the listener over the local socket.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PermissionQuery {
+    pub chat_session_id: String,
+    pub tool_name: String,
+    pub input: serde_json::Value,
+}
+
+/// Sent back by the listener once the browser answered (or the request timed out).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionDecision {
+    pub allow: bool,
+    pub message: Option<String>,
+}
+
+impl PermissionDecision {
+    pub fn deny(message: impl Into<String>) -> Self {
+        Self {
+            allow: false,
+            message: Some(message.into()),
+        }
+    }
+}
+
+type PendingMap = Arc<Mutex<HashMap<String, oneshot::Sender<PermissionDecision>>>>;
+
+/// Relays permission prompts from claude's MCP tool to the browser and back.
+#[derive(Clone)]
+pub struct PermissionBroker {
+    socket_path: PathBuf,
+    pending: PendingMap,
+}
+
+impl PermissionBroker {
+    pub fn start(out_tx: mpsc::Sender<ChatMessage>, timeout: Duration) -> Result<Self> {
+        let dir = daemon::gateway_dir();
+        fs::create_dir_all(&dir)?;
+        let socket_path = dir.join(format!("permission-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&socket_path);
+
+        let listener = UnixListener::bind(&socket_path)?;
+        fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))?;
+
+        let broker = Self {
+            socket_path,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        };
+
+        let accept_broker = broker.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let broker = accept_broker.clone();
+                let out_tx = out_tx.clone();
+                tokio::spawn(async move {
+                    broker.serve(stream, &out_tx, timeout).await;
+                });
+            }
+        });
+
+        Ok(broker)
+    }
+
+    /// MCP config passed to claude via `--mcp-config` for one chat session.
+    pub fn mcp_config(&self, chat_session_id: &str) -> serde_json::Value {
+        let exe = std::env::current_exe()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|_| "teeclaude".to_string());
+        serde_json::json!({
+            "mcpServers": {
+                MCP_SERVER_NAME: {
+                    "command": exe,
+                    "args": [
+                        "permission-mcp",
+                        "--socket",
+                        self.socket_path.to_string_lossy(),
+                        "--session",
+                        chat_session_id,
+                    ],
+                }
+            }
+        })
+    }
+
+    pub async fn resolve(&self, request_id: &str, decision: PermissionDecision) {
+        match self.pending.lock().await.remove(request_id) {
+            Some(tx) => {
+                let _ = tx.send(decision);
+            }
+            None => eprintln!("[permission] no pending request: {}", request_id),
+        }
+    }
+
+    /// Socket the MCP permission server connects to.
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    pub fn cleanup(&self) {
+        let _ = fs::remove_file(&self.socket_path);
+    }
+
+    async fn serve(&self, stream: UnixStream, out_tx: &mpsc::Sender<ChatMessage>, timeout: Duration) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let decision = match serde_json::from_str::<PermissionQuery>(&line) {
+                Ok(query) => self.ask(query, out_tx, timeout).await,
+                Err(e) => PermissionDecision::deny(format!("invalid permission query: {}", e)),
+            };
+            let Ok(mut json) = serde_json::to_string(&decision) else {
+                break;
+            };
+            json.push('\n');
+            if writer.write_all(json.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    async fn ask(
+        &self,
+        query: PermissionQuery,
+        out_tx: &mpsc::Sender<ChatMessage>,
+        timeout: Duration,
+    ) -> PermissionDecision {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(request_id.clone(), tx);
+
+        eprintln!(
+            "[permission] request: session={}, tool={}, id={}",
+            query.chat_session_id, query.tool_name, request_id
+        );
+
+        let _ = out_tx
+            .send(ChatMessage::permission_request(
+                &query.chat_session_id,
+                &request_id,
+                &query.tool_name,
+                query.input,
+            ))
+            .await;
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(decision)) => decision,
+            _ => {
+                self.pending.lock().await.remove(&request_id);
+                eprintln!("[permission] request timed out: id={}", request_id);
+                PermissionDecision::deny("permission request timed out")
+            }
+        }
+    }
+}
+
+/// Fully qualified tool name for `--permission-prompt-tool`.
+pub fn prompt_tool_name() -> String {
+    format!("mcp__{}__{}", MCP_SERVER_NAME, MCP_TOOL_NAME)
+}
@@ -1,11 +1,16 @@
 use crate::ws_client::{OptionalWs, SharedWs, WsClient};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use teeclaude_common::TerminalMessage;
-use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use std::fs::{File, OpenOptions};
 use std::io::{IsTerminal, Read, Write};
 use std::os::unix::io::AsRawFd;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use termios::{tcsetattr, Termios, TCSANOW};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
 pub async fn run(command: &str, args: &[String], server_url: &str, name: Option<&str>) -> Result<()> {
@@ -51,7 +56,7 @@ pub async fn run(command: &str, args: &[String], server_url: &str, name: Option<
     };
 
     // Create channel for PTY writes (both local and remote input)
-    let (pty_write_tx, mut pty_write_rx) = tokio::sync::mpsc::channel::<Vec<u8>>(1000);
+    let (pty_write_tx, pty_write_rx) = mpsc::channel::<Vec<u8>>(1000);
 
     // Setup stdin forwarding (local terminal → channel)
     let local_tx = pty_write_tx.clone();
@@ -72,33 +77,12 @@ pub async fn run(command: &str, args: &[String], server_url: &str, name: Option<
     });
 
     // Setup remote input forwarding (WebSocket → channel)
-    if let Some(mut rx) = input_rx {
-        let remote_tx = pty_write_tx.clone();
-        tokio::spawn(async move {
-            while let Some(msg) = rx.recv().await {
-                if let TerminalMessage::Input { content, .. } = msg {
-                    // Remove trailing newlines from content
-                    let content = content.trim_end_matches(['\n', '\r']);
-                    // Send content
-                    let _ = remote_tx.send(content.as_bytes().to_vec()).await;
-                    // Small delay then send Enter (\r)
-                    tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
-                    let _ = remote_tx.send(vec![b'\r']).await;
-                }
-            }
-        });
+    if let Some(rx) = input_rx {
+        tokio::spawn(forward_remote_input(rx, pty_write_tx.clone()));
     }
 
     // Single writer task for PTY
-    let mut master_writer = pair.master.take_writer()?;
-    tokio::spawn(async move {
-        while let Some(data) = pty_write_rx.recv().await {
-            if master_writer.write_all(&data).is_err() {
-                break;
-            }
-            let _ = master_writer.flush();
-        }
-    });
+    spawn_pty_writer(pair.master.as_ref(), pty_write_rx)?;
 
     // Read PTY output
     let mut reader = pair.master.try_clone_reader()?;
@@ -135,6 +119,253 @@ pub async fn run(command: &str, args: &[String], server_url: &str, name: Option<
     std::process::exit(status.exit_code().try_into().unwrap_or(1));
 }
 
+/// Size of PTYs that have no local terminal to follow.
+pub const HEADLESS_SIZE: PtySize = PtySize {
+    rows: 30,
+    cols: 120,
+    pixel_width: 0,
+    pixel_height: 0,
+};
+
+/// Printed by a headless session once it is connected; `daemon::detach` waits
+/// for it before reporting success.
+pub const HEADLESS_READY: &str = "Headless session";
+
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+
+/// Parse a terminal size given as `COLSxROWS`, such as `120x30`.
+pub fn parse_size(s: &str) -> Result<PtySize, String> {
+    let parsed = s.split_once('x').and_then(|(cols, rows)| {
+        let cols: u16 = cols.parse().ok().filter(|&c| c > 0)?;
+        let rows: u16 = rows.parse().ok().filter(|&r| r > 0)?;
+        Some(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+    });
+    parsed.ok_or_else(|| format!("expected COLSxROWS, such as 120x30, got {:?}", s))
+}
+
+/// Wrap a command without a local terminal: input comes only from the
+/// browser, and output goes to the server and, optionally, `log`.
+pub async fn run_headless(
+    command: &str,
+    args: &[String],
+    server_url: &str,
+    name: Option<&str>,
+    size: PtySize,
+    log: Option<&Path>,
+) -> Result<()> {
+    let log = match log {
+        Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+        None => None,
+    };
+
+    let mut cmd = CommandBuilder::new(command);
+    cmd.args(args);
+    if let Ok(cwd) = std::env::current_dir() {
+        cmd.cwd(cwd);
+    }
+    let full_command = std::iter::once(command.to_string())
+        .chain(args.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let session = start_headless(cmd, &full_command, server_url, name, size, log).await?;
+    eprintln!("{} {} running: {}", HEADLESS_READY, session.session_id, full_command);
+
+    let code = session.done.await.unwrap_or(1);
+    std::process::exit(code);
+}
+
+/// A PTY session started without a local terminal.
+pub struct Headless {
+    pub session_id: String,
+    pub killer: Box<dyn ChildKiller + Send + Sync>,
+    /// Finishes with the command's exit code
+    pub done: JoinHandle<i32>,
+}
+
+/// Run a command in a PTY that is only reachable through the server. The
+/// command keeps running while the connection to the server is re-established.
+pub async fn spawn_headless(
+    cmd: CommandBuilder,
+    display_command: &str,
+    server_url: &str,
+    name: Option<&str>,
+) -> Result<Headless> {
+    start_headless(cmd, display_command, server_url, name, HEADLESS_SIZE, None).await
+}
+
+/// Start a headless PTY session. The first connection must succeed; after
+/// that the session survives dropped connections.
+async fn start_headless(
+    cmd: CommandBuilder,
+    display_command: &str,
+    server_url: &str,
+    name: Option<&str>,
+    size: PtySize,
+    mut log: Option<File>,
+) -> Result<Headless> {
+    let session_id = Uuid::new_v4().to_string();
+    let ws_client = WsClient::try_connect(server_url, &session_id)
+        .await
+        .ok_or_else(|| anyhow!("could not connect to the terminal server"))?;
+
+    let pair = native_pty_system().openpty(size)?;
+    let mut child = pair.slave.spawn_command(cmd)?;
+    drop(pair.slave);
+    let killer = child.clone_killer();
+
+    // The relay outlives any single connection, so the PTY talks to it
+    let (out_tx, out_rx) = mpsc::channel::<TerminalMessage>(1000);
+    let (in_tx, in_rx) = mpsc::channel::<TerminalMessage>(100);
+    let start = TerminalMessage::session_start(&session_id, display_command, name.map(String::from));
+    tokio::spawn(relay_headless(
+        server_url.to_string(),
+        session_id.clone(),
+        start,
+        ws_client,
+        out_rx,
+        in_tx,
+    ));
+
+    let (pty_write_tx, pty_write_rx) = mpsc::channel::<Vec<u8>>(1000);
+    spawn_pty_writer(pair.master.as_ref(), pty_write_rx)?;
+    tokio::spawn(forward_remote_input(in_rx, pty_write_tx));
+
+    let mut reader = pair.master.try_clone_reader()?;
+    let id = session_id.clone();
+    let done = tokio::task::spawn_blocking(move || {
+        // Keep the master open until the command is done with it
+        let _master = pair.master;
+        let mut buf = [0u8; 4096];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if let Some(file) = &mut log {
+                        let _ = file.write_all(&buf[..n]);
+                    }
+                    let content = String::from_utf8_lossy(&buf[..n]).to_string();
+                    let _ = out_tx.try_send(TerminalMessage::output(&id, content));
+                }
+            }
+        }
+        let _ = out_tx.blocking_send(TerminalMessage::session_end(&id));
+        match child.wait() {
+            Ok(status) => status.exit_code().try_into().unwrap_or(1),
+            Err(_) => 1,
+        }
+    });
+
+    Ok(Headless {
+        session_id,
+        killer,
+        done,
+    })
+}
+
+/// Relay a headless session between its PTY and the server, reconnecting with
+/// backoff whenever the connection drops. Output produced while disconnected
+/// is dropped. Ends when `out_rx` closes, i.e. when the session is over.
+async fn relay_headless(
+    server_url: String,
+    session_id: String,
+    start: TerminalMessage,
+    mut client: WsClient,
+    mut out_rx: mpsc::Receiver<TerminalMessage>,
+    in_tx: mpsc::Sender<TerminalMessage>,
+) {
+    let mut delay = RECONNECT_MIN;
+    loop {
+        let connected_at = Instant::now();
+        // Announce the session again so the server picks it back up
+        let _ = client.output_tx.send(start.clone()).await;
+        loop {
+            tokio::select! {
+                msg = out_rx.recv() => match msg {
+                    Some(msg) => {
+                        if client.output_tx.send(msg).await.is_err() {
+                            break;
+                        }
+                    }
+                    // Dropping the client closes the socket once it is flushed
+                    None => return,
+                },
+                msg = client.input_rx.recv() => match msg {
+                    Some(msg) => {
+                        let _ = in_tx.send(msg).await;
+                    }
+                    None => break,
+                },
+            }
+        }
+
+        eprintln!("[pty] session={} lost the terminal server, reconnecting", session_id);
+        // Keep backing off from a server that accepts and then drops us
+        if connected_at.elapsed() >= RECONNECT_MAX {
+            delay = RECONNECT_MIN;
+        }
+        client = loop {
+            let wait = tokio::time::sleep(delay);
+            tokio::pin!(wait);
+            loop {
+                tokio::select! {
+                    _ = &mut wait => break,
+                    msg = out_rx.recv() => {
+                        if msg.is_none() {
+                            return;
+                        }
+                    }
+                }
+            }
+            delay = (delay * 2).min(RECONNECT_MAX);
+            if let Some(client) = WsClient::try_connect(&server_url, &session_id).await {
+                break client;
+            }
+        };
+        eprintln!("[pty] session={} reconnected", session_id);
+    }
+}
+
+/// Type remote input into the PTY, one line at a time.
+async fn forward_remote_input(
+    mut rx: mpsc::Receiver<TerminalMessage>,
+    tx: mpsc::Sender<Vec<u8>>,
+) {
+    while let Some(msg) = rx.recv().await {
+        if let TerminalMessage::Input { content, .. } = msg {
+            // Remove trailing newlines from content
+            let content = content.trim_end_matches(['\n', '\r']);
+            // Send content
+            let _ = tx.send(content.as_bytes().to_vec()).await;
+            // Small delay then send Enter (\r)
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            let _ = tx.send(vec![b'\r']).await;
+        }
+    }
+}
+
+fn spawn_pty_writer(
+    master: &dyn MasterPty,
+    mut rx: mpsc::Receiver<Vec<u8>>,
+) -> Result<()> {
+    let mut master_writer = master.take_writer()?;
+    tokio::spawn(async move {
+        while let Some(data) = rx.recv().await {
+            if master_writer.write_all(&data).is_err() {
+                break;
+            }
+            let _ = master_writer.flush();
+        }
+    });
+    Ok(())
+}
+
 fn get_terminal_size() -> PtySize {
     if let Some((cols, rows)) = term_size::dimensions() {
         PtySize {
@@ -0,0 +1,161 @@
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::attachments::SCRATCH_DIR;
+use crate::config::Sandbox;
+
+/// Files and directories under `$HOME` that claude needs to work.
+const CLAUDE_HOME_STATE: &[&str] = &[".claude", ".claude.json", ".config/claude"];
+
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+/// Directory of an executable after resolving symlinks, so versioned installs
+/// such as `~/.local/bin/claude -> ~/.local/share/claude/versions/...` work.
+fn install_dir(exe: &Path) -> Option<PathBuf> {
+    std::fs::canonicalize(exe).ok()?.parent().map(Path::to_path_buf)
+}
+
+/// Admin dir under `<app_root>/.git/worktrees` of the worktree at `cwd`, read
+/// from the worktree's `.git` file.
+fn worktree_admin_dir(app_root: &str, cwd: &str) -> Option<PathBuf> {
+    let link = std::fs::read_to_string(Path::new(cwd).join(".git")).ok()?;
+    let admin = std::fs::canonicalize(link.strip_prefix("gitdir:")?.trim()).ok()?;
+    let worktrees = std::fs::canonicalize(Path::new(app_root).join(".git").join("worktrees")).ok()?;
+    (admin.parent() == Some(worktrees.as_path())).then_some(admin)
+}
+
+/// A command that runs `program` in `cwd` inside a bubblewrap sandbox for
+/// `app_root`. Arguments added by the caller go to `program`. The listener's
+/// `config_path` stays read-only even inside the app root, and `socket` is the
+/// permission prompt socket when prompts are enabled.
+pub fn command(
+    program: &str,
+    app_root: &str,
+    cwd: &str,
+    sandbox: &Sandbox,
+    config_path: &Path,
+    socket: Option<&Path>,
+) -> Result<Command> {
+    if !cfg!(target_os = "linux") {
+        bail!("the sandbox is only supported on Linux");
+    }
+    let Some(bwrap) = find_in_path("bwrap") else {
+        bail!("the sandbox requires bubblewrap (bwrap) in PATH");
+    };
+    let program_path = match Path::new(program) {
+        path if path.is_absolute() => path.to_path_buf(),
+        _ => match find_in_path(program) {
+            Some(path) => path,
+            None => bail!("{} not found in PATH", program),
+        },
+    };
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+
+    let mut cmd = Command::new(bwrap);
+    cmd.args(["--die-with-parent", "--new-session", "--unshare-pid", "--unshare-ipc"]);
+    cmd.args(["--ro-bind", "/", "/"]);
+    cmd.args(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]);
+
+    let bind = |cmd: &mut Command, flag: &str, path: &Path| {
+        cmd.arg(flag).arg(path).arg(path);
+    };
+
+    if let Some(home) = &home {
+        // Hide keys and credentials in the home directory
+        cmd.arg("--tmpfs").arg(home);
+        for entry in CLAUDE_HOME_STATE {
+            bind(&mut cmd, "--bind-try", &home.join(entry));
+        }
+    }
+    // Only this listener's socket; other files in the gateway dir stay hidden
+    if let Some(socket) = socket {
+        bind(&mut cmd, "--bind-try", socket);
+    }
+
+    // claude itself and the teeclaude binary it runs as the permission MCP server
+    let mut executables = vec![program_path.clone()];
+    executables.extend(std::env::current_exe().ok());
+    let mut exe_dirs: Vec<PathBuf> = Vec::new();
+    for exe in &executables {
+        let dirs = [exe.parent().map(Path::to_path_buf), install_dir(exe)];
+        for dir in dirs.into_iter().flatten() {
+            if !exe_dirs.contains(&dir) {
+                exe_dirs.push(dir);
+            }
+        }
+    }
+    for dir in &exe_dirs {
+        bind(&mut cmd, "--ro-bind-try", dir);
+    }
+
+    for path in &sandbox.read_only {
+        bind(&mut cmd, "--ro-bind-try", path);
+    }
+    for path in &sandbox.read_write {
+        bind(&mut cmd, "--bind-try", path);
+    }
+    bind(&mut cmd, "--bind", Path::new(app_root));
+    // The config and scratch dir decide what the agent may do, so they must
+    // not be writable from inside; an isolated session's worktree lives in
+    // the scratch dir and is made writable again
+    bind(&mut cmd, "--ro-bind-try", config_path);
+    bind(&mut cmd, "--ro-bind-try", &Path::new(app_root).join(SCRATCH_DIR));
+    // The listener runs git in the app outside the sandbox, so hooks and git
+    // config written from inside would escape it
+    bind(&mut cmd, "--ro-bind-try", &Path::new(app_root).join(".git"));
+    if Path::new(cwd) != Path::new(app_root) {
+        bind(&mut cmd, "--bind", Path::new(cwd));
+        bind(&mut cmd, "--ro-bind-try", &Path::new(cwd).join(".git"));
+        // A worktree keeps its index and HEAD in its own admin dir; the files
+        // pointing back at the repository stay read-only
+        if let Some(admin) = worktree_admin_dir(app_root, cwd) {
+            bind(&mut cmd, "--bind", &admin);
+            for entry in ["commondir", "gitdir", "config.worktree"] {
+                bind(&mut cmd, "--ro-bind-try", &admin.join(entry));
+            }
+        }
+    }
+
+    cmd.arg("--chdir").arg(cwd);
+    cmd.arg("--").arg(program_path);
+    Ok(cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::git;
+
+    #[tokio::test]
+    async fn worktree_admin_dir_stays_in_the_app_repository() {
+        let dir = std::env::temp_dir().join(format!("teeclaude-sandbox-{}", uuid::Uuid::new_v4()));
+        let app = dir.join("app");
+        std::fs::create_dir_all(&app).unwrap();
+        git(&app, &["init", "-q"]).await.unwrap();
+        let commit = ["-c", "user.email=test@example.com", "-c", "user.name=test", "commit", "-q", "--allow-empty", "-m", "x"];
+        git(&app, &commit).await.unwrap();
+        let tree = app.join(SCRATCH_DIR).join("worktrees").join("s1");
+        git(&app, &["worktree", "add", "-q", "-b", "s1", &tree.to_string_lossy()]).await.unwrap();
+        // A directory whose .git points somewhere else
+        let other = dir.join("other");
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(other.join(".git"), format!("gitdir: {}\n", dir.display())).unwrap();
+
+        let app_root = app.to_string_lossy().to_string();
+        let admin = worktree_admin_dir(&app_root, &tree.to_string_lossy());
+        let plain = worktree_admin_dir(&app_root, &app_root);
+        let elsewhere = worktree_admin_dir(&app_root, &other.to_string_lossy());
+        let expected = std::fs::canonicalize(app.join(".git/worktrees/s1")).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(admin, Some(expected));
+        assert_eq!(plain, None);
+        assert_eq!(elsewhere, None);
+    }
+}
@@ -0,0 +1,314 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use teeclaude_common::ChatMessage;
+
+use crate::apps;
+use crate::attachments::AttachmentStore;
+use crate::chat_handler::{self, ChatTurn, RunningTurns};
+use crate::config::{ConfigHandle, Schedule};
+use crate::permission::PermissionBroker;
+
+/// A five-field cron expression (`minute hour day-of-month month day-of-week`)
+/// evaluated in local time. Fields take `*`, numbers, ranges `a-b`, steps
+/// `/n` and comma-separated lists; Sunday is 0 or 7.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cron {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Both day fields are restricted, so either one matching is enough
+    either_day: bool,
+}
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let expanded = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("cron expression needs 5 fields: {:?}", expr);
+        };
+        let field_err = |name: &str, e: anyhow::Error| anyhow!("{} in {:?}: {}", name, expr, e);
+
+        let mut weekdays = parse_field(weekday, 0, 7).map_err(|e| field_err("day of week", e))?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            expr: expr.trim().to_string(),
+            minutes: parse_field(minute, 0, 59).map_err(|e| field_err("minute", e))?,
+            hours: parse_field(hour, 0, 23).map_err(|e| field_err("hour", e))?,
+            days: parse_field(day, 1, 31).map_err(|e| field_err("day of month", e))?,
+            months: parse_field(month, 1, 12).map_err(|e| field_err("month", e))?,
+            weekdays,
+            either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.expr
+    }
+
+    pub fn matches(&self, time: &DateTime<Local>) -> bool {
+        let has = |set: u64, value: u32| set & (1 << value) != 0;
+        let day = has(self.days, time.day());
+        let weekday = has(self.weekdays, time.weekday().num_days_from_sunday());
+        let day_matches = if self.either_day {
+            day || weekday
+        } else {
+            day && weekday
+        };
+        has(self.minutes, time.minute())
+            && has(self.hours, time.hour())
+            && has(self.months, time.month())
+            && day_matches
+    }
+}
+
+impl TryFrom<String> for Cron {
+    type Error = anyhow::Error;
+
+    fn try_from(expr: String) -> Result<Self> {
+        Self::parse(&expr)
+    }
+}
+
+impl From<Cron> for String {
+    fn from(cron: Cron) -> Self {
+        cron.expr
+    }
+}
+
+/// Parse one field into a bit set of the values it allows.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let number = |s: &str| -> Result<u32> {
+        let n: u32 = s.parse().map_err(|_| anyhow!("invalid value {:?}", s))?;
+        if n < min || n > max {
+            bail!("{} is outside {}-{}", n, min, max);
+        }
+        Ok(n)
+    };
+
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (part, None),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (number(a)?, number(b)?),
+                // `5/15` runs from 5 to the end of the range
+                None if step.is_some() => (number(range)?, max),
+                None => {
+                    let n = number(range)?;
+                    (n, n)
+                }
+            },
+        };
+        if start > end {
+            bail!("empty range {:?}", range);
+        }
+        let step = match step {
+            Some(s) => s
+                .parse::<u32>()
+                .ok()
+                .filter(|&s| s > 0)
+                .ok_or_else(|| anyhow!("invalid step {:?}", s))?,
+            None => 1,
+        };
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// Run the configured schedules until the listener stops. Each due schedule
+/// starts a new chat session, whether or not a browser is connected.
+pub async fn run(
+    config: ConfigHandle,
+    out_tx: mpsc::Sender<ChatMessage>,
+    permissions: PermissionBroker,
+    attachments: AttachmentStore,
+    running: RunningTurns,
+) {
+    let mut last_minute = None;
+    loop {
+        let now = Local::now();
+        // Wake just after the next minute starts
+        let wait = 60 - now.second() as u64;
+        tokio::time::sleep(Duration::from_secs(wait)).await;
+
+        let now = Local::now();
+        let Some(minute) = now.with_second(0).and_then(|t| t.with_nanosecond(0)) else {
+            continue;
+        };
+        if last_minute.is_some_and(|last| minute <= last) {
+            continue;
+        }
+        last_minute = Some(minute);
+
+        let schedules = match config.read(|c| c.schedules.clone()).await {
+            Ok(s) => s,
+            Err(_) => return,
+        };
+        for schedule in schedules.into_iter().filter(|s| s.cron.matches(&minute)) {
+            let (config, out_tx, permissions, attachments, running) = (
+                config.clone(),
+                out_tx.clone(),
+                permissions.clone(),
+                attachments.clone(),
+                running.clone(),
+            );
+            tokio::spawn(async move {
+                start(&config, &out_tx, &permissions, &attachments, &running, schedule).await;
+            });
+        }
+    }
+}
+
+async fn start(
+    config: &ConfigHandle,
+    out_tx: &mpsc::Sender<ChatMessage>,
+    permissions: &PermissionBroker,
+    attachments: &AttachmentStore,
+    running: &RunningTurns,
+    schedule: Schedule,
+) {
+    let app_root = match apps::resolve_app_root(config, &schedule.app_root).await {
+        Ok(root) => root,
+        Err(e) => {
+            eprintln!("[schedule] skipped {:?}: {}", schedule.cron.as_str(), e);
+            return;
+        }
+    };
+    eprintln!("[schedule] running {:?} in {}", schedule.cron.as_str(), app_root);
+    let turn = ChatTurn {
+        chat_session_id: uuid::Uuid::new_v4().to_string(),
+        new_session: true,
+        app_root,
+        content: schedule.prompt,
+        options: None,
+        attachments: Vec::new(),
+        permission_mode: schedule.permission_mode,
+    };
+    chat_handler::handle_chat_input(config, out_tx, permissions, attachments, running, turn).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    fn values(set: u64) -> Vec<u32> {
+        (0..64).filter(|v| set & (1 << v) != 0).collect()
+    }
+
+    #[test]
+    fn fields_take_ranges_steps_and_lists() {
+        assert_eq!(values(parse_field("*", 0, 5).unwrap()), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(values(parse_field("1-3", 0, 59).unwrap()), vec![1, 2, 3]);
+        assert_eq!(values(parse_field("*/15", 0, 59).unwrap()), vec![0, 15, 30, 45]);
+        assert_eq!(values(parse_field("10-20/5", 0, 59).unwrap()), vec![10, 15, 20]);
+        assert_eq!(values(parse_field("50/5", 0, 59).unwrap()), vec![50, 55]);
+        assert_eq!(values(parse_field("1,5,7-8", 0, 59).unwrap()), vec![1, 5, 7, 8]);
+    }
+
+    #[test]
+    fn invalid_fields_are_rejected() {
+        for field in ["", "60", "5-1", "*/0", "*/x", "a", "1-", "-1", "1,,2"] {
+            assert!(parse_field(field, 0, 59).is_err(), "{:?}", field);
+        }
+        assert!(parse_field("0", 1, 31).is_err());
+    }
+
+    #[test]
+    fn expressions_need_five_fields() {
+        assert!(Cron::parse("* * * *").is_err());
+        assert!(Cron::parse("* * * * * *").is_err());
+        assert!(Cron::parse("@yearly").is_err());
+        assert!(Cron::parse(" 0 2 * * 1-5 ").is_ok());
+    }
+
+    #[test]
+    fn aliases_expand() {
+        assert_eq!(Cron::parse("@hourly").unwrap().minutes, 1);
+        let daily = Cron::parse("@daily").unwrap();
+        assert!(daily.matches(&at(2026, 3, 4, 0, 0)));
+        assert!(!daily.matches(&at(2026, 3, 4, 1, 0)));
+        assert_eq!(Cron::parse("@midnight").unwrap().hours, daily.hours);
+        // 2026-03-01 is a Sunday
+        let weekly = Cron::parse("@weekly").unwrap();
+        assert!(weekly.matches(&at(2026, 3, 1, 0, 0)));
+        assert!(!weekly.matches(&at(2026, 3, 2, 0, 0)));
+        let monthly = Cron::parse("@monthly").unwrap();
+        assert!(monthly.matches(&at(2026, 4, 1, 0, 0)));
+        assert!(!monthly.matches(&at(2026, 4, 2, 0, 0)));
+        assert_eq!(monthly.as_str(), "@monthly");
+    }
+
+    #[test]
+    fn sunday_is_zero_or_seven() {
+        let sunday = at(2026, 3, 1, 9, 0);
+        assert!(Cron::parse("0 9 * * 7").unwrap().matches(&sunday));
+        assert!(Cron::parse("0 9 * * 0").unwrap().matches(&sunday));
+        assert!(Cron::parse("0 9 * * 5-7").unwrap().matches(&sunday));
+        assert!(!Cron::parse("0 9 * * 1-6").unwrap().matches(&sunday));
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either_day() {
+        // The 15th, or any Monday
+        let cron = Cron::parse("0 12 15 * 1").unwrap();
+        assert!(cron.matches(&at(2026, 3, 15, 12, 0))); // Sunday the 15th
+        assert!(cron.matches(&at(2026, 3, 2, 12, 0))); // Monday the 2nd
+        assert!(!cron.matches(&at(2026, 3, 3, 12, 0))); // Tuesday the 3rd
+    }
+
+    #[test]
+    fn a_wildcard_day_field_must_still_match() {
+        // Weekdays only, on any day of the month
+        let cron = Cron::parse("30 8 * * 1-5").unwrap();
+        assert!(cron.matches(&at(2026, 3, 2, 8, 30)));
+        assert!(!cron.matches(&at(2026, 3, 1, 8, 30)));
+        // The 1st of the month, whatever the weekday; `*/2` counts as a wildcard
+        let cron = Cron::parse("0 0 1 * */2").unwrap();
+        assert!(!cron.either_day);
+        assert!(!cron.matches(&at(2026, 3, 2, 0, 0)));
+    }
+
+    #[test]
+    fn minute_hour_and_month_must_all_match() {
+        let cron = Cron::parse("*/20 9-17 * 1,7 *").unwrap();
+        assert!(cron.matches(&at(2026, 1, 5, 9, 40)));
+        assert!(!cron.matches(&at(2026, 1, 5, 9, 41)));
+        assert!(!cron.matches(&at(2026, 1, 5, 18, 0)));
+        assert!(!cron.matches(&at(2026, 2, 5, 9, 40)));
+    }
+
+    #[test]
+    fn crons_round_trip_through_serde() {
+        let cron: Cron = serde_json::from_str("\"0 2 * * 1-5\"").unwrap();
+        assert_eq!(serde_json::to_string(&cron).unwrap(), "\"0 2 * * 1-5\"");
+        assert!(serde_json::from_str::<Cron>("\"0 25 * * *\"").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{anyhow, bail, Result};
+use std::path::Path;
+use tokio::sync::mpsc;
+
+use teeclaude_common::ChatMessage;
+
+use crate::attachments;
+use crate::chat_handler::{RunningTurns, TurnSlot};
+use crate::config::ConfigHandle;
+use crate::listener;
+use crate::transcript;
+use crate::worktree;
+
+const MAX_NAME_LEN: usize = 200;
+
+pub async fn rename(config: &ConfigHandle, chat_session_id: &str, name: &str) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("session name must not be empty");
+    }
+    let name: String = name.chars().take(MAX_NAME_LEN).collect();
+
+    let id = chat_session_id.to_string();
+    let found = config
+        .update(move |c| match c.session_mut(&id) {
+            Some(session) => {
+                session.name = name;
+                true
+            }
+            None => false,
+        })
+        .await?;
+    if !found {
+        bail!("unknown chat session");
+    }
+    Ok(())
+}
+
+pub async fn archive(config: &ConfigHandle, chat_session_id: &str, archived: bool) -> Result<()> {
+    let id = chat_session_id.to_string();
+    let found = config
+        .update(move |c| match c.session_mut(&id) {
+            Some(session) => {
+                session.archived = archived;
+                true
+            }
+            None => false,
+        })
+        .await?;
+    if !found {
+        bail!("unknown chat session");
+    }
+    Ok(())
+}
+
+/// Delete a session. An isolated session's worktree and branch go with it,
+/// unless another session still works there.
+pub async fn delete(
+    config: &ConfigHandle,
+    running: &RunningTurns,
+    chat_session_id: &str,
+    remove_transcript: bool,
+) -> Result<()> {
+    // Holding the slot keeps new turns out while the worktree goes away
+    let _slot = TurnSlot::reserve(running, chat_session_id)
+        .ok_or_else(|| anyhow!("a turn is running in this session"))?;
+
+    let id = chat_session_id.to_string();
+    let found = config
+        .read(move |c| {
+            c.apps.iter().find_map(|a| {
+                let s = a.sessions.iter().find(|s| s.id == id)?;
+                Some((a.root.clone(), s.worktree.clone()))
+            })
+        })
+        .await?;
+    if let Some((app_root, Some(worktree))) = found {
+        if Path::new(&worktree.path).is_dir() && !worktree::is_shared(config, chat_session_id, &worktree).await? {
+            worktree::discard(&app_root, &worktree).await?;
+            eprintln!("[sessions] removed worktree of {}, branch={}", chat_session_id, worktree.branch);
+        }
+    }
+
+    let id = chat_session_id.to_string();
+    let (cwd, app_root) = config
+        .update(move |c| Some((c.session_cwd(&id)?, c.remove_session(&id)?)))
+        .await?
+        .ok_or_else(|| anyhow!("unknown chat session"))?;
+
+    if let Err(e) = attachments::remove_session(&app_root, chat_session_id).await {
+        eprintln!("[sessions] failed to remove attachments of {}: {}", chat_session_id, e);
+    }
+
+    if remove_transcript {
+        let path = transcript::transcript_path(&cwd, chat_session_id);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => eprintln!("[sessions] removed transcript {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => bail!("failed to remove transcript {}: {}", path.display(), e),
+        }
+    }
+    Ok(())
+}
+
+/// Fork a session and announce the new one. Claude copies the conversation
+/// when the fork's first turn starts; a fork of an isolated session works in
+/// a worktree of its own.
+pub async fn fork(config: &ConfigHandle, out_tx: &mpsc::Sender<ChatMessage>, chat_session_id: &str) -> Result<()> {
+    let id = chat_session_id.to_string();
+    let (app_root, parent_cwd, parent_worktree, source) = config
+        .read(move |c| {
+            let (app, session) = c.apps.iter().find_map(|a| {
+                let s = a.sessions.iter().find(|s| s.id == id)?;
+                Some((a, s))
+            })?;
+            let source = session.pending_fork.clone().unwrap_or_else(|| session.id.clone());
+            Some((app.root.clone(), c.session_cwd(&id)?, session.worktree.clone(), source))
+        })
+        .await?
+        .ok_or_else(|| anyhow!("unknown chat session"))?;
+
+    let fork_id = uuid::Uuid::new_v4().to_string();
+    let worktree = match &parent_worktree {
+        Some(parent) => {
+            let worktree = worktree::fork(&app_root, parent, &fork_id).await?;
+            // Claude looks up the conversation to fork under the new cwd
+            if let Err(e) = copy_transcript(&parent_cwd, &worktree.path, &source).await {
+                let _ = worktree::discard(&app_root, &worktree).await;
+                return Err(e);
+            }
+            Some(worktree)
+        }
+        None => None,
+    };
+
+    let (id, new_id, new_worktree) = (chat_session_id.to_string(), fork_id.clone(), worktree.clone());
+    let forked = config
+        .update(move |c| c.fork_session(&id, &new_id, new_worktree))
+        .await;
+    let (app_root, fork) = match forked {
+        Ok(Some(f)) => f,
+        result => {
+            if let Some(worktree) = &worktree {
+                let _ = worktree::discard(&app_root, worktree).await;
+            }
+            result?;
+            bail!("unknown chat session");
+        }
+    };
+    eprintln!("[sessions] forked session={} into {}", chat_session_id, fork.id);
+
+    let _ = out_tx
+        .send(ChatMessage::chat_session_created(
+            &fork.id,
+            app_root,
+            &fork.name,
+            fork.worktree.map(|w| w.branch),
+            fork.forked_from,
+        ))
+        .await;
+    Ok(())
+}
+
+async fn copy_transcript(from_cwd: &str, to_cwd: &str, chat_session_id: &str) -> Result<()> {
+    let from = transcript::transcript_path(from_cwd, chat_session_id);
+    let to = transcript::transcript_path(to_cwd, chat_session_id);
+    if let Some(dir) = to.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    match tokio::fs::copy(&from, &to).await {
+        Ok(_) => Ok(()),
+        // Nothing to copy when the session never finished a turn
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => bail!("failed to copy transcript {}: {}", from.display(), e),
+    }
+}
+
+/// Report the outcome of a session command: errors go to the session,
+/// successes are broadcast as a fresh session list.
+pub async fn report(
+    config: &ConfigHandle,
+    out_tx: &mpsc::Sender<ChatMessage>,
+    chat_session_id: &str,
+    result: Result<()>,
+) {
+    match result {
+        Ok(()) => listener::send_listener_ready(config, out_tx).await,
+        Err(e) => {
+            eprintln!("[sessions] session={}: {}", chat_session_id, e);
+            let _ = out_tx
+                .send(ChatMessage::chat_error(chat_session_id, e.to_string()))
+                .await;
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::{bail, Result};
+use portable_pty::{ChildKiller, CommandBuilder};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use teeclaude_common::ChatMessage;
+
+use crate::apps;
+use crate::config::ConfigHandle;
+use crate::pty;
+use crate::sandbox;
+use crate::url;
+
+type Killers = Arc<Mutex<HashMap<String, Box<dyn ChildKiller + Send + Sync>>>>;
+
+/// Terminals opened from chat on `server`, keyed by terminal session id. They
+/// are killed when the listener stops, since nothing else would end them.
+#[derive(Clone)]
+pub struct Terminals {
+    server: String,
+    killers: Killers,
+}
+
+impl Terminals {
+    pub fn new(server: &str) -> Self {
+        Self {
+            server: server.to_string(),
+            killers: Killers::default(),
+        }
+    }
+
+    pub fn kill_all(&self) {
+        for (session_id, mut killer) in self.killers.lock().unwrap().drain() {
+            eprintln!("[terminal] killing session={}", session_id);
+            let _ = killer.kill();
+        }
+    }
+}
+
+/// Start a terminal in an app, attached to the server under `terminal_token`,
+/// and return its terminal session id. Sandboxed apps get a sandboxed shell.
+async fn open(
+    config: &ConfigHandle,
+    terminals: &Terminals,
+    app_root: &str,
+    command: Option<&str>,
+    terminal_token: &str,
+) -> Result<String> {
+    if terminal_token.is_empty() {
+        bail!("the server did not provide a terminal token");
+    }
+    let app_root = apps::resolve_app_root(config, app_root).await?;
+    let snapshot = config.snapshot().await?;
+
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+    let mut cmd = match snapshot.app_sandbox(&app_root) {
+        Some(sandbox) => {
+            let bwrap = sandbox::command(
+                &shell,
+                &app_root,
+                &app_root,
+                &sandbox,
+                &snapshot.config_path,
+                None,
+            )?;
+            let mut cmd = CommandBuilder::new(bwrap.get_program());
+            cmd.args(bwrap.get_args());
+            cmd
+        }
+        None => CommandBuilder::new(&shell),
+    };
+    if let Some(command) = command {
+        cmd.args(["-c", command]);
+    }
+    cmd.cwd(&app_root);
+    // The daemon has no terminal of its own to inherit this from
+    cmd.env("TERM", "xterm-256color");
+
+    let display_command = command.unwrap_or(&shell);
+    let name = app_root.rsplit('/').next().unwrap_or(&app_root);
+    let ws_url = url::build_ws_url(&terminals.server, Some(terminal_token), "/ws/wrapper");
+    let session = pty::spawn_headless(cmd, display_command, &ws_url, Some(name)).await?;
+    eprintln!(
+        "[terminal] opened {:?} in {}: session={}",
+        display_command, app_root, session.session_id
+    );
+
+    let session_id = session.session_id;
+    terminals
+        .killers
+        .lock()
+        .unwrap()
+        .insert(session_id.clone(), session.killer);
+    let (killers, id) = (terminals.killers.clone(), session_id.clone());
+    tokio::spawn(async move {
+        let _ = session.done.await;
+        killers.lock().unwrap().remove(&id);
+    });
+    Ok(session_id)
+}
+
+pub async fn handle_open_terminal(
+    config: &ConfigHandle,
+    terminals: &Terminals,
+    out_tx: &mpsc::Sender<ChatMessage>,
+    request_id: &str,
+    app_root: &str,
+    command: Option<&str>,
+    terminal_token: &str,
+) {
+    let message = match open(config, terminals, app_root, command, terminal_token).await {
+        Ok(session_id) => ChatMessage::TerminalOpened {
+            request_id: request_id.to_string(),
+            terminal_token: terminal_token.to_string(),
+            session_id,
+        },
+        Err(e) => {
+            eprintln!("[terminal] failed to open a terminal in {}: {}", app_root, e);
+            ChatMessage::request_failed(request_id, e.to_string())
+        }
+    };
+    let _ = out_tx.send(message).await;
+}
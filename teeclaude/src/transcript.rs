@@ -0,0 +1,105 @@
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+use teeclaude_common::ChatMessage;
+
+use crate::config::ConfigHandle;
+
+const DEFAULT_PAGE_SIZE: usize = 200;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Claude's config directory (`$CLAUDE_CONFIG_DIR` or `~/.claude`).
+fn claude_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("CLAUDE_CONFIG_DIR") {
+        return PathBuf::from(dir);
+    }
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join(".claude")
+}
+
+/// Claude stores transcripts under `projects/<cwd with non-alphanumerics as '-'>/`.
+pub fn transcript_path(cwd: &str, chat_session_id: &str) -> PathBuf {
+    let project: String = cwd
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    claude_dir()
+        .join("projects")
+        .join(project)
+        .join(format!("{}.jsonl", chat_session_id))
+}
+
+struct Page {
+    entries: Vec<String>,
+    cursor: Option<usize>,
+    total: usize,
+}
+
+/// Read conversation entries (user/assistant lines) ending just before `before`.
+async fn read_page(path: &Path, before: Option<usize>, limit: usize) -> Result<Page> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let entries: Vec<&str> = content
+        .lines()
+        .filter(|line| is_conversation_entry(line))
+        .collect();
+
+    let total = entries.len();
+    let end = before.unwrap_or(total).min(total);
+    let start = end.saturating_sub(limit);
+
+    Ok(Page {
+        entries: entries[start..end].iter().map(|s| s.to_string()).collect(),
+        cursor: (start > 0).then_some(start),
+        total,
+    })
+}
+
+fn is_conversation_entry(line: &str) -> bool {
+    let Ok(v) = serde_json::from_str::<serde_json::Value>(line) else {
+        return false;
+    };
+    matches!(
+        v.get("type").and_then(|t| t.as_str()),
+        Some("user") | Some("assistant")
+    )
+}
+
+pub async fn handle_load_transcript(
+    config: &ConfigHandle,
+    out_tx: &mpsc::Sender<ChatMessage>,
+    chat_session_id: &str,
+    before: Option<usize>,
+    limit: Option<usize>,
+) {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let result = async {
+        let id = chat_session_id.to_string();
+        let cwd = config
+            .read(move |c| c.session_cwd(&id))
+            .await?
+            .ok_or_else(|| anyhow!("unknown chat session"))?;
+        let path = transcript_path(&cwd, chat_session_id);
+        read_page(&path, before, limit)
+            .await
+            .map_err(|e| anyhow!("failed to read transcript {}: {}", path.display(), e))
+    }
+    .await;
+
+    let message = match result {
+        Ok(page) => ChatMessage::ChatHistory {
+            chat_session_id: chat_session_id.to_string(),
+            entries: page.entries,
+            cursor: page.cursor,
+            total: page.total,
+        },
+        Err(e) => {
+            eprintln!("[transcript] session={}: {}", chat_session_id, e);
+            ChatMessage::chat_error(chat_session_id, e.to_string())
+        }
+    };
+    let _ = out_tx.send(message).await;
+}
@@ -0,0 +1,239 @@
+use serde_json::Value;
+
+use teeclaude_common::{NotifyLevel, UiCommand};
+
+const OPEN_TAG: &str = "<ui_call>";
+const CLOSE_TAG: &str = "</ui_call>";
+
+type Parser = fn(&[Value]) -> Result<UiCommand, String>;
+
+/// Known `ui_call` commands. A call is a JSON array whose first element is the
+/// command name and the rest are its arguments, e.g. `["open_file", "src/main.rs", 12]`.
+const COMMANDS: &[(&str, Parser)] = &[
+    ("refresh_apps", refresh_apps),
+    ("open_file", open_file),
+    ("show_diff", show_diff),
+    ("notify", notify),
+];
+
+fn refresh_apps(_args: &[Value]) -> Result<UiCommand, String> {
+    Ok(UiCommand::RefreshApps)
+}
+
+fn open_file(args: &[Value]) -> Result<UiCommand, String> {
+    let path = string_arg(args, 0, "path")?;
+    let line = match args.get(1) {
+        None | Some(Value::Null) => None,
+        Some(v) => Some(
+            v.as_u64()
+                .and_then(|n| u32::try_from(n).ok())
+                .ok_or("line must be a positive integer")?,
+        ),
+    };
+    Ok(UiCommand::OpenFile { path, line })
+}
+
+fn show_diff(args: &[Value]) -> Result<UiCommand, String> {
+    let paths = (0..args.len())
+        .map(|i| string_arg(args, i, "path"))
+        .collect::<Result<_, _>>()?;
+    Ok(UiCommand::ShowDiff { paths })
+}
+
+fn notify(args: &[Value]) -> Result<UiCommand, String> {
+    let message = string_arg(args, 0, "message")?;
+    let level = match args.get(1) {
+        None => NotifyLevel::default(),
+        Some(v) => serde_json::from_value(v.clone())
+            .map_err(|_| format!("unknown notify level: {}", v))?,
+    };
+    Ok(UiCommand::Notify { message, level })
+}
+
+fn string_arg(args: &[Value], index: usize, name: &str) -> Result<String, String> {
+    args.get(index)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| format!("{} must be a string", name))
+}
+
+/// Parse the body of one `<ui_call>` block.
+pub fn parse(body: &str) -> Result<UiCommand, String> {
+    let call: Vec<Value> =
+        serde_json::from_str(body.trim()).map_err(|e| format!("invalid ui_call {:?}: {}", body, e))?;
+    let name = call
+        .first()
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("ui_call without a command name: {}", body))?;
+    let (_, parser) = COMMANDS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .ok_or_else(|| format!("unknown ui_call command: {}", name))?;
+    parser(&call[1..])
+}
+
+/// Remove `<ui_call>` blocks from `text`, returning the remaining text and the
+/// block bodies. A line left empty by the removal is dropped entirely.
+fn strip(text: &str) -> (String, Vec<String>) {
+    let mut calls = Vec::new();
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(OPEN_TAG) {
+        let Some(len) = rest[start..].find(CLOSE_TAG) else {
+            break;
+        };
+        calls.push(rest[start + OPEN_TAG.len()..start + len].to_string());
+        out.push_str(&rest[..start]);
+        rest = &rest[start + len + CLOSE_TAG.len()..];
+        let line_start = out.rfind('\n').map_or(0, |i| i + 1);
+        if out[line_start..].trim().is_empty() && rest.trim_start_matches([' ', '\t']).starts_with('\n') {
+            out.truncate(line_start);
+            rest = &rest.trim_start_matches([' ', '\t'])[1..];
+        }
+    }
+    out.push_str(rest);
+    (out, calls)
+}
+
+/// Extract `ui_call` blocks from a stream-json line. Text blocks of assistant
+/// messages and the final `result` are stripped; the line is only rewritten
+/// when it contained a call.
+pub fn extract(line: &str) -> (String, Vec<String>) {
+    if !line.contains(OPEN_TAG) {
+        return (line.to_string(), Vec::new());
+    }
+    let Ok(mut event) = serde_json::from_str::<Value>(line) else {
+        return (line.to_string(), Vec::new());
+    };
+
+    let mut calls = Vec::new();
+    let mut changed = false;
+    let mut strip_field = |value: &mut Value, calls: &mut Vec<String>| {
+        if let Some(text) = value.as_str() {
+            let (text, found) = strip(text);
+            if !found.is_empty() {
+                calls.extend(found);
+                *value = Value::String(text);
+                changed = true;
+            }
+        }
+    };
+
+    let kind = event.get("type").and_then(Value::as_str).map(str::to_string);
+    match kind.as_deref() {
+        Some("assistant") => {
+            if let Some(blocks) = event
+                .pointer_mut("/message/content")
+                .and_then(Value::as_array_mut)
+            {
+                for block in blocks.iter_mut() {
+                    if block.get("type").and_then(Value::as_str) == Some("text") {
+                        if let Some(text) = block.get_mut("text") {
+                            strip_field(text, &mut calls);
+                        }
+                    }
+                }
+                blocks.retain(|b| {
+                    b.get("type").and_then(Value::as_str) != Some("text")
+                        || b.get("text").and_then(Value::as_str).is_some_and(|t| !t.trim().is_empty())
+                });
+            }
+        }
+        Some("result") => {
+            // The final answer repeats calls already taken from the assistant message
+            if let Some(result) = event.get_mut("result") {
+                strip_field(result, &mut Vec::new());
+            }
+        }
+        _ => {}
+    }
+
+    if !changed {
+        return (line.to_string(), Vec::new());
+    }
+    (event.to_string(), calls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn strip_removes_blocks_and_their_lines() {
+        let text = "Opening it now.\n<ui_call>[\"refresh_apps\"]</ui_call>\nDone <ui_call>[\"notify\", \"hi\"]</ui_call>!";
+        let (text, calls) = strip(text);
+        assert_eq!(text, "Opening it now.\nDone !");
+        assert_eq!(calls, vec!["[\"refresh_apps\"]", "[\"notify\", \"hi\"]"]);
+    }
+
+    #[test]
+    fn strip_leaves_unterminated_blocks() {
+        let text = "before <ui_call>[\"refresh_apps\"] and no end";
+        assert_eq!(strip(text), (text.to_string(), Vec::new()));
+
+        // A complete block is still taken before an unterminated one
+        let (text, calls) = strip("<ui_call>[\"refresh_apps\"]</ui_call> then <ui_call>[");
+        assert_eq!(text, " then <ui_call>[");
+        assert_eq!(calls, vec!["[\"refresh_apps\"]"]);
+    }
+
+    #[test]
+    fn nested_blocks_end_at_the_first_close_tag() {
+        let (text, calls) = strip("<ui_call>[\"a\", <ui_call>[\"refresh_apps\"]</ui_call>]</ui_call>");
+        assert_eq!(text, "]</ui_call>");
+        assert_eq!(calls, vec!["[\"a\", <ui_call>[\"refresh_apps\"]"]);
+        assert!(parse(&calls[0]).is_err());
+    }
+
+    #[test]
+    fn parse_known_commands() {
+        assert!(matches!(
+            parse(" [\"open_file\", \"src/main.rs\", 12] "),
+            Ok(UiCommand::OpenFile { path, line: Some(12) }) if path == "src/main.rs"
+        ));
+        assert!(matches!(
+            parse("[\"show_diff\", \"a.rs\", \"b.rs\"]"),
+            Ok(UiCommand::ShowDiff { paths }) if paths == ["a.rs", "b.rs"]
+        ));
+        assert!(matches!(parse("[\"refresh_apps\"]"), Ok(UiCommand::RefreshApps)));
+    }
+
+    #[test]
+    fn parse_rejects_bad_calls() {
+        assert!(parse("[]").is_err());
+        assert!(parse("{\"open_file\": 1}").is_err());
+        assert!(parse("[\"format_disk\"]").is_err());
+        assert!(parse("[\"open_file\", \"a.rs\", -1]").is_err());
+        assert!(parse("[\"notify\", \"hi\", \"shout\"]").is_err());
+    }
+
+    #[test]
+    fn extract_rewrites_assistant_text_only() {
+        let line = json!({
+            "type": "assistant",
+            "message": {"content": [
+                {"type": "text", "text": "<ui_call>[\"refresh_apps\"]</ui_call>"},
+                {"type": "tool_use", "name": "Bash", "input": {"command": "echo <ui_call>"}},
+            ]},
+        })
+        .to_string();
+        let (rewritten, calls) = extract(&line);
+        assert_eq!(calls, vec!["[\"refresh_apps\"]"]);
+        let event: Value = serde_json::from_str(&rewritten).unwrap();
+        let blocks = event["message"]["content"].as_array().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0]["type"], "tool_use");
+    }
+
+    #[test]
+    fn extract_drops_calls_repeated_in_the_result() {
+        let line = json!({"type": "result", "result": "ok <ui_call>[\"refresh_apps\"]</ui_call>"}).to_string();
+        let (rewritten, calls) = extract(&line);
+        assert!(calls.is_empty());
+        assert_eq!(serde_json::from_str::<Value>(&rewritten).unwrap()["result"], "ok ");
+
+        let plain = "{\"type\":\"user\",\"text\":\"<ui_call>\"}";
+        assert_eq!(extract(plain), (plain.to_string(), Vec::new()));
+    }
+}
@@ -22,3 +22,22 @@ pub fn build_ws_url(server: &str, token: Option<&str>, path: &str) -> String {
         None => url,
     }
 }
+
+/// HTTP(S) URL of a server API endpoint; `server` may be given in any of the
+/// forms `build_ws_url` accepts.
+pub fn build_http_url(server: &str, path: &str) -> String {
+    let base = server.trim_end_matches('/');
+    let base = base.split("/ws/").next().unwrap_or(base);
+
+    let http_base = if base.starts_with("ws://") {
+        base.replacen("ws://", "http://", 1)
+    } else if base.starts_with("wss://") {
+        base.replacen("wss://", "https://", 1)
+    } else if base.starts_with("http://") || base.starts_with("https://") {
+        base.to_string()
+    } else {
+        format!("https://{}", base)
+    };
+
+    format!("{}{}", http_base, path)
+}
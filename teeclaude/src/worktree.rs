@@ -0,0 +1,190 @@
+use anyhow::{anyhow, bail, Result};
+use std::path::Path;
+use tokio::sync::mpsc;
+
+use teeclaude_common::ChatMessage;
+
+use crate::attachments;
+use crate::chat_handler::{RunningTurns, TurnSlot};
+use crate::config::{ConfigHandle, Worktree};
+use crate::git::git;
+use crate::listener;
+
+/// Create a worktree and branch for a new isolated session from the app's HEAD.
+pub async fn create(app_root: &str, session_id: &str) -> Result<Worktree> {
+    let root = Path::new(app_root);
+    if git(root, &["rev-parse", "--verify", "-q", "HEAD"]).await.is_err() {
+        bail!("isolated sessions need a git repository with at least one commit");
+    }
+    create_at(app_root, session_id, "HEAD").await
+}
+
+async fn create_at(app_root: &str, session_id: &str, base: &str) -> Result<Worktree> {
+    let short_id: String = session_id.chars().take(8).collect();
+    let branch = format!("teeclaude/{}", short_id);
+    let path = attachments::scratch_dir(app_root)
+        .join("worktrees")
+        .join(session_id)
+        .to_string_lossy()
+        .to_string();
+    git(Path::new(app_root), &["worktree", "add", "-q", "-b", &branch, &path, base]).await?;
+    eprintln!("[worktree] created {} on branch {}", path, branch);
+    Ok(Worktree { path, branch })
+}
+
+/// Give a fork of an isolated session its own worktree and branch, starting
+/// from the parent's HEAD with the parent's uncommitted changes copied over.
+pub async fn fork(app_root: &str, parent: &Worktree, session_id: &str) -> Result<Worktree> {
+    let parent_tree = Path::new(&parent.path);
+    if !parent_tree.is_dir() {
+        bail!("the session's worktree has already been removed");
+    }
+    let head = git(parent_tree, &["rev-parse", "HEAD"]).await?;
+    // A stash-like commit of tracked changes; empty when the tree is clean
+    let changes = git(parent_tree, &["stash", "create"]).await?;
+    let untracked = git(parent_tree, &["ls-files", "--others", "--exclude-standard", "-z"]).await?;
+
+    let worktree = create_at(app_root, session_id, head.trim()).await?;
+    let copied = async {
+        let tree = Path::new(&worktree.path);
+        if !changes.trim().is_empty() {
+            git(tree, &["stash", "apply", "-q", changes.trim()]).await?;
+        }
+        for path in untracked.split('\0').filter(|p| !p.is_empty()) {
+            let target = tree.join(path);
+            if let Some(dir) = target.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            tokio::fs::copy(parent_tree.join(path), &target).await?;
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    if let Err(e) = copied {
+        let _ = discard(app_root, &worktree).await;
+        bail!("failed to copy the session's changes: {}", e);
+    }
+    Ok(worktree)
+}
+
+/// Remove the worktree and delete its branch.
+pub async fn discard(app_root: &str, worktree: &Worktree) -> Result<()> {
+    let root = Path::new(app_root);
+    git(root, &["worktree", "remove", "--force", &worktree.path]).await?;
+    git(root, &["branch", "-D", &worktree.branch]).await?;
+    Ok(())
+}
+
+/// Commit what the session left uncommitted, merge its branch into the app's
+/// checked-out branch and clean up. A conflicting merge is aborted and the
+/// worktree kept.
+async fn merge(app_root: &str, worktree: &Worktree, session_name: &str) -> Result<()> {
+    let tree = Path::new(&worktree.path);
+    if !git(tree, &["status", "--porcelain"]).await?.trim().is_empty() {
+        git(tree, &["add", "-A"]).await?;
+        let message = format!("teeclaude: {}", session_name);
+        git(tree, &["commit", "-q", "--no-verify", "-m", &message]).await?;
+    }
+
+    let root = Path::new(app_root);
+    if let Err(e) = git(root, &["merge", "--no-ff", "--no-edit", "--no-verify", &worktree.branch]).await {
+        let _ = git(root, &["merge", "--abort"]).await;
+        bail!("{}; the session branch was left as is", e);
+    }
+    discard(app_root, worktree).await
+}
+
+/// Whether another live session works in the same worktree, as forks made
+/// before they had worktrees of their own may.
+pub async fn is_shared(config: &ConfigHandle, chat_session_id: &str, worktree: &Worktree) -> Result<bool> {
+    let (id, path) = (chat_session_id.to_string(), worktree.path.clone());
+    config
+        .read(move |c| {
+            c.apps.iter().flat_map(|a| &a.sessions).any(|s| {
+                s.id != id && !s.archived && s.worktree.as_ref().is_some_and(|w| w.path == path)
+            })
+        })
+        .await
+}
+
+async fn resolve(
+    config: &ConfigHandle,
+    running: &RunningTurns,
+    chat_session_id: &str,
+    merge_branch: bool,
+) -> Result<String> {
+    // Holding the slot keeps new turns out while the worktree goes away
+    let _slot = TurnSlot::reserve(running, chat_session_id)
+        .ok_or_else(|| anyhow!("a turn is running in this session"))?;
+
+    let id = chat_session_id.to_string();
+    let (app_root, worktree, name) = config
+        .read(move |c| {
+            c.apps.iter().find_map(|a| {
+                let s = a.sessions.iter().find(|s| s.id == id)?;
+                Some((a.root.clone(), s.worktree.clone(), s.name.clone()))
+            })
+        })
+        .await?
+        .ok_or_else(|| anyhow!("unknown chat session"))?;
+    let worktree = worktree.ok_or_else(|| anyhow!("session is not isolated"))?;
+    if is_shared(config, chat_session_id, &worktree).await? {
+        bail!("another session works in the same worktree; archive it first");
+    }
+    if !Path::new(&worktree.path).is_dir() {
+        bail!("the session's worktree has already been removed");
+    }
+
+    if merge_branch {
+        merge(&app_root, &worktree, &name).await?;
+    } else {
+        discard(&app_root, &worktree).await?;
+    }
+
+    let id = chat_session_id.to_string();
+    config
+        .update(move |c| {
+            if let Some(session) = c.session_mut(&id) {
+                session.archived = true;
+            }
+        })
+        .await?;
+    Ok(worktree.branch)
+}
+
+/// Merge or discard an isolated session, answering the correlated request.
+pub async fn handle_resolve(
+    config: &ConfigHandle,
+    out_tx: &mpsc::Sender<ChatMessage>,
+    running: &RunningTurns,
+    request_id: &str,
+    chat_session_id: &str,
+    merge_branch: bool,
+) {
+    let action = if merge_branch { "merge" } else { "discard" };
+    let message = match resolve(config, running, chat_session_id, merge_branch).await {
+        Ok(branch) => {
+            eprintln!("[worktree] {} session={}, branch={}", action, chat_session_id, branch);
+            listener::send_listener_ready(config, out_tx).await;
+            let (request_id, chat_session_id) = (request_id.to_string(), chat_session_id.to_string());
+            if merge_branch {
+                ChatMessage::SessionMerged {
+                    request_id,
+                    chat_session_id,
+                    branch,
+                }
+            } else {
+                ChatMessage::SessionDiscarded {
+                    request_id,
+                    chat_session_id,
+                    branch,
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("[worktree] failed to {} session={}: {}", action, chat_session_id, e);
+            ChatMessage::request_failed(request_id, e.to_string())
+        }
+    };
+    let _ = out_tx.send(message).await;
+}
@@ -29,6 +29,8 @@ impl WsClient {
                     break;
                 }
             }
+            // All senders are gone: the session is over
+            let _ = ws_sender.close().await;
         });
 
         let session_id_clone = session_id.clone();
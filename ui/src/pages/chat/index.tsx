@@ -1,7 +1,7 @@
 import { useState, useRef, useEffect } from 'react'
 import { useToken } from '../../lib/use-token'
 import { useChat } from './use-chat'
-import type { ChatEntry } from './types'
+import type { ChatEntry, PermissionRequestMessage } from './types'
 import styles from './style.module.scss'
 
 export function ChatPage() {
@@ -33,7 +33,9 @@ function ChatContent({ token, commandHint }: { token: string; commandHint: strin
     sessions,
     currentSessionId,
     messages,
+    permissions,
     sendMessage,
+    respondPermission,
     selectSession,
     startNewSession,
   } = useChat(token)
@@ -48,6 +50,8 @@ function ChatContent({ token, commandHint }: { token: string; commandHint: strin
   const sessionArray = Array.from(sessions.values())
   const currentMessages = currentSessionId ? (messages.get(currentSessionId) ?? []) : []
   const currentSession = currentSessionId ? sessions.get(currentSessionId) : null
+  const currentPermissions = permissions.filter(p => p.chat_session_id === currentSessionId)
+  const waitingSessions = new Set(permissions.map(p => p.chat_session_id))
   const activeAppName = currentSession
     ? apps.find(a => a.root === currentSession.app_root)?.name
     : apps[0]?.name
@@ -97,7 +101,9 @@ function ChatContent({ token, commandHint }: { token: string; commandHint: strin
                         onClick={() => { selectSession(session.id); setSidebarOpen(false) }}
                       >
                         <span className={styles.sessionName}>{session.name || session.id.slice(0, 8)}</span>
-                        {session.status === 'streaming' && <span className={styles.streamingDot} />}
+                        {waitingSessions.has(session.id)
+                          ? <span className={styles.permissionDot} title="Waiting for approval" />
+                          : session.status === 'streaming' && <span className={styles.streamingDot} />}
                       </button>
                     ))}
                     <button
@@ -138,6 +144,9 @@ function ChatContent({ token, commandHint }: { token: string; commandHint: strin
         />
 
         <div className={styles.inputWrapper}>
+          {currentPermissions.map(request => (
+            <PermissionPrompt key={request.request_id} request={request} onRespond={respondPermission} />
+          ))}
           <ChatInput
             onSend={(content) => sendMessage(content, currentSession?.app_root)}
             disabled={currentSession?.status === 'streaming'}
@@ -207,6 +216,31 @@ function MessageList({ messages, isStreaming }: { messages: ChatEntry[]; isStrea
   )
 }
 
+function PermissionPrompt({
+  request,
+  onRespond,
+}: {
+  request: PermissionRequestMessage
+  onRespond: (requestId: string, allow: boolean) => void
+}) {
+  return (
+    <div className={styles.permissionPrompt}>
+      <div className={styles.permissionTitle}>
+        Claude wants to use <strong>{request.tool_name}</strong>
+      </div>
+      <pre className={styles.permissionInput}>{JSON.stringify(request.input, null, 2)}</pre>
+      <div className={styles.permissionActions}>
+        <button className={styles.denyButton} onClick={() => onRespond(request.request_id, false)}>
+          Deny
+        </button>
+        <button className={styles.allowButton} onClick={() => onRespond(request.request_id, true)}>
+          Allow
+        </button>
+      </div>
+    </div>
+  )
+}
+
 function ChatInput({ onSend, disabled }: { onSend: (content: string) => void; disabled?: boolean }) {
   const [value, setValue] = useState('')
   const textareaRef = useRef<HTMLTextAreaElement>(null)
@@ -43,12 +43,22 @@ export type ChatSessionCreatedMessage = {
   timestamp: string
 }
 
+export type PermissionRequestMessage = {
+  type: 'permission_request'
+  chat_session_id: string
+  request_id: string
+  tool_name: string
+  input: unknown
+  timestamp: string
+}
+
 export type ChatMessage =
   | ListenerReadyMessage
   | ChatOutputMessage
   | ChatDoneMessage
   | ChatErrorMessage
   | ChatSessionCreatedMessage
+  | PermissionRequestMessage
 
 export type ChatSessionData = {
   id: string
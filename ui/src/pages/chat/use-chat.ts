@@ -1,5 +1,5 @@
 import { useEffect, useRef, useState, useCallback } from 'react'
-import type { AppInfo, ChatMessage, ChatEntry, ChatSessionData } from './types'
+import type { AppInfo, ChatMessage, ChatEntry, ChatSessionData, PermissionRequestMessage } from './types'
 import { loadChatSessions, saveChatSession } from './db'
 import type { ChatSessionRecord } from './db'
 
@@ -9,6 +9,8 @@ type ChatState = {
   sessions: Map<string, ChatSessionData>
   currentSessionId: string | null
   connected: boolean
+  // Tool uses waiting for approval, in the order they were requested
+  permissions: PermissionRequestMessage[]
 }
 
 export function useChat(token: string | null) {
@@ -18,6 +20,7 @@ export function useChat(token: string | null) {
     sessions: new Map(),
     currentSessionId: null,
     connected: false,
+    permissions: [],
   })
   const [messages, setMessages] = useState<Map<string, ChatEntry[]>>(new Map())
 
@@ -175,6 +178,8 @@ export function useChat(token: string | null) {
           next.set(message.chat_session_id, entries)
           return next
         })
+      } else if (message.type === 'permission_request') {
+        setState(prev => ({ ...prev, permissions: [...prev.permissions, message] }))
       } else if (message.type === 'chat_done') {
         setState(prev => {
           const sessions = new Map(prev.sessions)
@@ -182,7 +187,9 @@ export function useChat(token: string | null) {
           if (session) {
             sessions.set(message.chat_session_id, { ...session, status: 'done' })
           }
-          return { ...prev, sessions }
+          // Requests left unanswered were denied when the turn ended
+          const permissions = prev.permissions.filter(p => p.chat_session_id !== message.chat_session_id)
+          return { ...prev, sessions, permissions }
         })
         setMessages(prev => {
           const next = new Map(prev)
@@ -214,7 +221,8 @@ export function useChat(token: string | null) {
               error: message.error,
             })
           }
-          return { ...prev, sessions }
+          const permissions = prev.permissions.filter(p => p.chat_session_id !== message.chat_session_id)
+          return { ...prev, sessions, permissions }
         })
         setMessages(prev => {
           const next = new Map(prev)
@@ -299,10 +307,35 @@ export function useChat(token: string | null) {
     })
 
     if (!res.ok) {
-      console.error('Failed to send chat input:', res.status)
+      // Refused messages (e.g. an unknown app) are answered here, not over SSE
+      const body = await res.json().catch(() => null)
+      const error: string = body?.error ?? `request failed (${res.status})`
+      console.error('Failed to send chat input:', error)
+      if (sessionId) {
+        setMessages(prev => {
+          const next = new Map(prev)
+          const entries = [...(next.get(sessionId) ?? [])]
+          entries.push({ role: 'assistant', content: '', status: 'error', error })
+          next.set(sessionId, entries)
+          return next
+        })
+      }
     }
   }, [token])
 
+  const respondPermission = useCallback((requestId: string, allow: boolean) => {
+    if (!token) return
+    setState(prev => ({
+      ...prev,
+      permissions: prev.permissions.filter(p => p.request_id !== requestId),
+    }))
+    fetch('/api/chat/permission', {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ token, request_id: requestId, allow }),
+    }).catch(console.error)
+  }, [token])
+
   const selectSession = useCallback((sessionId: string) => {
     setState(prev => ({ ...prev, currentSessionId: sessionId }))
   }, [])
@@ -316,6 +349,7 @@ export function useChat(token: string | null) {
     ...state,
     messages,
     sendMessage,
+    respondPermission,
     selectSession,
     startNewSession,
   }